@@ -1,55 +1,427 @@
 use anyhow::{anyhow, Result};
 use colored::Colorize;
-use log::{error, info};
-use rustyline::{error::ReadlineError, DefaultEditor};
+use futures::future::join_all;
+use futures::StreamExt;
+use log::{debug, error, info, warn};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rustyline::{error::ReadlineError, history::DefaultHistory, Editor};
 use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
 
-use crate::clients::OpenAIClient;
+use crate::cli::commands::{
+    parse_arg, parse_command, print_help, ChatHelper, CommandSpec, DEFAULT_TRANSCRIPT_PATH,
+};
+use crate::clients::{
+    create_chat_provider, create_embedding_provider, create_reranker, ChatSession,
+    EmbeddingProvider, Reranker, Transcript,
+};
 use crate::config::{
-    DEFAULT_CHAT_MODEL, DEFAULT_EMBEDDING_MODEL, EMOJI_AI, EMOJI_CONTEXT, EMOJI_ERROR,
-    EMOJI_SEARCH, QDRANT_COLLECTION, validate_environment,
+    validate_environment, CONTEXT_NEIGHBOURS, DEFAULT_EMBEDDING_MODEL, EMOJI_AI, EMOJI_CONTEXT,
+    EMOJI_ERROR, EMOJI_SEARCH, HISTORY_STRATEGY, HYBRID_SEARCH, MMR_LAMBDA, QDRANT_COLLECTION,
+    QUERY_REWRITE, RERANK_FETCH_K,
 };
-use crate::services::qdrant_service::QdrantService;
+use crate::services::filter::{split_filter_terms, Filter};
+use crate::services::qdrant_service::{cosine_similarity, QdrantService, SearchHit};
+use crate::services::sparse::Bm25Encoder;
 
-/// Get relevant context for a query.
-async fn get_context(
+/// Describe where a chunk comes from, including its location within the source.
+fn describe_source(payload: &HashMap<String, Value>) -> String {
+    let mut description = match payload.get("source") {
+        Some(Value::String(source)) => source.clone(),
+        _ => String::from("unknown source"),
+    };
+
+    if let Some(page) = payload.get("page").and_then(|p| p.as_u64()) {
+        description.push_str(&format!(" p.{}", page));
+    }
+    if let Some(Value::String(section)) = payload.get("section") {
+        description.push_str(&format!(", section \"{}\"", section));
+    }
+    if let Some(Value::String(symbol)) = payload.get("symbol") {
+        match payload.get("symbol_kind") {
+            Some(Value::String(kind)) => description.push_str(&format!(", {} {}", kind, symbol)),
+            _ => description.push_str(&format!(", {}", symbol)),
+        }
+    }
+    if let (Some(start), Some(end)) = (
+        payload.get("start_line").and_then(|l| l.as_u64()),
+        payload.get("end_line").and_then(|l| l.as_u64()),
+    ) {
+        description.push_str(&format!(", lines {}-{}", start, end));
+    }
+
+    description
+}
+
+/// Settings of the retrieval pipeline used by `get_context`.
+struct RetrievalSettings {
+    /// Number of chunks added to the prompt.
+    top_k: u64,
+    score_threshold: f32,
+    filter: Filter,
+    /// Optional second stage rescoring an over-fetched list of candidates.
+    reranker: Option<Box<dyn Reranker>>,
+    /// Trade-off between relevance (1.0) and diversity (0.0) when selecting
+    /// chunks with maximal marginal relevance; `None` keeps the ranking as is.
+    mmr_lambda: Option<f32>,
+    /// Number of candidates fetched for the reranker or MMR selection.
+    fetch_k: u64,
+    /// Number of chunks on each side of a hit merged into its passage.
+    neighbours: u64,
+    /// Condense follow-up questions into standalone queries before searching.
+    rewrite_query: bool,
+}
+
+/// Rescore search hits with a reranker and sort them by the new scores.
+///
+/// If the reranker fails, the original ranking is kept so the turn still gets context.
+async fn rerank_hits(
+    reranker: &dyn Reranker,
+    query: &str,
+    mut hits: Vec<SearchHit>,
+) -> Vec<SearchHit> {
+    let documents: Vec<String> = hits
+        .iter()
+        .map(|hit| {
+            hit.payload
+                .get("chunk_text")
+                .and_then(|text| text.as_str())
+                .unwrap_or_default()
+                .to_string()
+        })
+        .collect();
+
+    match reranker.score(query, &documents).await {
+        Ok(scores) => {
+            for (hit, score) in hits.iter_mut().zip(scores) {
+                hit.rerank_score = Some(score);
+            }
+            // Stable sort, so ties keep the search order
+            hits.sort_by(|a, b| b.ranking_score().total_cmp(&a.ranking_score()));
+            debug!(
+                "Reranked {} candidates with {}",
+                documents.len(),
+                reranker.name()
+            );
+        }
+        Err(e) => warn!(
+            "Reranking with {} failed, keeping search order: {}",
+            reranker.name(),
+            e
+        ),
+    }
+
+    hits
+}
+
+/// Pick `top_k` hits by maximal marginal relevance.
+///
+/// Each step takes the hit maximising `lambda * relevance - (1 - lambda) * redundancy`,
+/// where relevance is the hit's ranking score scaled to 0..1 across the candidates and
+/// redundancy is its highest cosine similarity to an already selected hit.
+/// Hits without a vector are never considered redundant.
+fn select_mmr(hits: Vec<SearchHit>, top_k: usize, lambda: f32) -> Vec<SearchHit> {
+    let (min, max) = hits
+        .iter()
+        .map(SearchHit::ranking_score)
+        .fold((f32::MAX, f32::MIN), |(min, max), score| {
+            (min.min(score), max.max(score))
+        });
+    let relevance = |score: f32| {
+        if max > min {
+            (score - min) / (max - min)
+        } else {
+            1.0
+        }
+    };
+
+    let mut candidates = hits;
+    let mut selected: Vec<SearchHit> = Vec::with_capacity(top_k.min(candidates.len()));
+    while selected.len() < top_k && !candidates.is_empty() {
+        let marginal_relevance = |hit: &SearchHit| {
+            let redundancy = match &hit.vector {
+                Some(vector) => selected
+                    .iter()
+                    .filter_map(|chosen| chosen.vector.as_deref())
+                    .map(|chosen| cosine_similarity(vector, chosen))
+                    .fold(0.0, f32::max),
+                None => 0.0,
+            };
+            lambda * relevance(hit.ranking_score()) - (1.0 - lambda) * redundancy
+        };
+
+        // On ties the earlier (better ranked) candidate wins
+        let mut best = 0;
+        let mut best_value = f32::MIN;
+        for (i, hit) in candidates.iter().enumerate() {
+            let value = marginal_relevance(hit);
+            if value > best_value {
+                best = i;
+                best_value = value;
+            }
+        }
+        selected.push(candidates.remove(best));
+    }
+
+    selected
+}
+
+/// Append a chunk to a passage, dropping text the chunker repeated as overlap.
+fn append_chunk(passage: &mut String, chunk: &str) {
+    const MIN_OVERLAP: usize = 16;
+
+    // Longest prefix of the chunk that ends the passage, on word boundaries
+    let overlap = (MIN_OVERLAP..=chunk.len().min(passage.len()))
+        .rev()
+        .filter(|&len| chunk.is_char_boundary(len))
+        .find(|&len| {
+            let start = passage.len() - len;
+            passage.ends_with(&chunk[..len])
+                && (start == 0 || passage[..start].ends_with(char::is_whitespace))
+                && (len == chunk.len() || chunk[len..].starts_with(char::is_whitespace))
+        })
+        .unwrap_or(0);
+
+    if overlap == 0 && !passage.is_empty() {
+        passage.push('\n');
+    }
+    passage.push_str(&chunk[overlap..]);
+}
+
+/// A run of consecutive chunks of one source around one or more hits.
+struct Passage {
+    hit: SearchHit,
+    /// Source, content hash and inclusive chunk index range, for hits that can be expanded.
+    span: Option<(String, String, u64, u64)>,
+}
+
+/// Widen each hit to the `neighbours` chunks before and after it in its source.
+///
+/// Hits whose windows overlap or touch are merged into one passage, which keeps
+/// the rank and score of the best hit. Record chunks (CSV rows, JSON records) are
+/// independent of each other and left as they are, as are hits without a content
+/// hash to tell their version apart and hits whose neighbours cannot be fetched.
+async fn expand_neighbours(
+    hits: Vec<SearchHit>,
+    qdrant_client: &QdrantService,
+    neighbours: u64,
+) -> Vec<SearchHit> {
+    let mut passages: Vec<Passage> = Vec::new();
+    for hit in hits {
+        let payload = &hit.payload;
+        let span = match (
+            payload.get("source").and_then(|s| s.as_str()),
+            payload.get("source_hash").and_then(|h| h.as_str()),
+            payload.get("chunk_index").and_then(|i| i.as_u64()),
+            payload.get("total_chunks").and_then(|t| t.as_u64()),
+        ) {
+            (Some(source), Some(hash), Some(index), Some(total))
+                if payload.get("chunker").and_then(|c| c.as_str()) != Some("record") =>
+            {
+                let last = (index + neighbours).min(total.saturating_sub(1)).max(index);
+                (
+                    source.to_string(),
+                    hash.to_string(),
+                    index.saturating_sub(neighbours),
+                    last,
+                )
+            }
+            _ => {
+                passages.push(Passage { hit, span: None });
+                continue;
+            }
+        };
+
+        let (source, hash, first, last) = &span;
+        let adjacent = passages.iter_mut().find(|passage| match &passage.span {
+            Some((other, other_hash, other_first, other_last)) => {
+                other == source
+                    && other_hash == hash
+                    && *first <= other_last + 1
+                    && *other_first <= last + 1
+            }
+            None => false,
+        });
+        match adjacent {
+            Some(Passage {
+                span: Some((_, _, other_first, other_last)),
+                ..
+            }) => {
+                *other_first = (*other_first).min(*first);
+                *other_last = (*other_last).max(*last);
+            }
+            _ => passages.push(Passage {
+                hit,
+                span: Some(span),
+            }),
+        }
+    }
+
+    let fetches = passages.iter().map(|passage| async move {
+        match &passage.span {
+            Some((source, hash, first, last)) => {
+                Some(qdrant_client.get_chunks(source, hash, *first..=*last).await)
+            }
+            None => None,
+        }
+    });
+    let fetched = join_all(fetches).await;
+
+    passages
+        .into_iter()
+        .zip(fetched)
+        .map(|(passage, chunks)| {
+            let mut hit = passage.hit;
+            let chunks = match chunks {
+                Some(Ok(chunks)) if !chunks.is_empty() => chunks,
+                Some(Err(e)) => {
+                    warn!(
+                        "Could not fetch neighbouring chunks, using the hit alone: {}",
+                        e
+                    );
+                    return hit;
+                }
+                _ => return hit,
+            };
+
+            let mut text = String::new();
+            for chunk in &chunks {
+                if let Some(Value::String(chunk_text)) = chunk.get("chunk_text") {
+                    append_chunk(&mut text, chunk_text);
+                }
+            }
+            hit.payload
+                .insert("chunk_text".to_string(), Value::String(text));
+
+            // Line ranges of code chunks now span the whole passage
+            if let Some(start) = chunks
+                .first()
+                .and_then(|chunk| chunk.get("start_line"))
+                .cloned()
+            {
+                hit.payload.insert("start_line".to_string(), start);
+            }
+            if let Some(end) = chunks
+                .last()
+                .and_then(|chunk| chunk.get("end_line"))
+                .cloned()
+            {
+                hit.payload.insert("end_line".to_string(), end);
+            }
+            hit
+        })
+        .collect()
+}
+
+/// Context retrieved for a query: the prompt text and the hits it was built from,
+/// numbered in order from `first_number`.
+struct RetrievedContext {
+    text: String,
+    hits: Vec<SearchHit>,
+    first_number: usize,
+}
+
+/// Search for candidate chunks, combining semantic and keyword matches when
+/// hybrid search is enabled.
+async fn search_candidates(
     query: &str,
-    openai_client: &OpenAIClient,
+    embedder: &dyn EmbeddingProvider,
     qdrant_client: &QdrantService,
-    top_k: u64,
+    settings: &RetrievalSettings,
+    limit: u64,
     score_threshold: f32,
-) -> Result<(bool, Option<String>)> {
+) -> Result<Vec<SearchHit>> {
     // Generate query embedding
+    let q_vec = embedder
+        .embed(&[query.to_string()])
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("No embedding returned for query"))?;
+
+    let filter = Some(&settings.filter);
+    let with_vector = settings.mmr_lambda.is_some();
+
+    if *HYBRID_SEARCH {
+        let sparse_query = Bm25Encoder::default().encode_query(query);
+        qdrant_client
+            .hybrid_search(
+                q_vec,
+                sparse_query,
+                limit,
+                score_threshold,
+                filter,
+                with_vector,
+            )
+            .await
+    } else {
+        qdrant_client
+            .search(q_vec, limit, score_threshold, filter, with_vector)
+            .await
+    }
+}
+
+/// Get relevant context for a query, numbering the contexts from `first_number`.
+async fn get_context(
+    query: &str,
+    embedder: &dyn EmbeddingProvider,
+    qdrant_client: &QdrantService,
+    settings: &RetrievalSettings,
+    first_number: usize,
+) -> Result<Option<RetrievedContext>> {
     info!("{} Searching for relevant information...", EMOJI_SEARCH);
-    let q_vec = openai_client.embed(&[query.to_string()]).await?[0].clone();
 
-    // Search for relevant chunks
-    let results = qdrant_client
-        .search(q_vec, top_k, score_threshold)
-        .await?;
+    // Over-fetch candidates when a reranker or MMR picks the final chunks
+    let limit = if settings.reranker.is_some() || settings.mmr_lambda.is_some() {
+        settings.fetch_k.max(settings.top_k)
+    } else {
+        settings.top_k
+    };
+    let mut results = search_candidates(
+        query,
+        embedder,
+        qdrant_client,
+        settings,
+        limit,
+        settings.score_threshold,
+    )
+    .await?;
+
+    if let Some(reranker) = &settings.reranker {
+        results = rerank_hits(reranker.as_ref(), query, results).await;
+    }
+    match settings.mmr_lambda {
+        Some(lambda) => results = select_mmr(results, settings.top_k as usize, lambda),
+        None => results.truncate(settings.top_k as usize),
+    }
+    if settings.neighbours > 0 {
+        results = expand_neighbours(results, qdrant_client, settings.neighbours).await;
+    }
+
+    // Only chunks with text can be shown to the model and cited
+    results.retain(|result| matches!(result.payload.get("chunk_text"), Some(Value::String(_))));
 
     if results.is_empty() {
         info!("{} No relevant context found", EMOJI_SEARCH);
-        return Ok((false, None));
+        return Ok(None);
     }
 
     // Prepare context from search results
     let mut context_parts = Vec::new();
     for (i, result) in results.iter().enumerate() {
-        let score = result.1;
-        let payload = &result.2;
+        let score = result.score;
+        let payload = &result.payload;
+        let text = payload
+            .get("chunk_text")
+            .and_then(|t| t.as_str())
+            .unwrap_or_default();
 
-        let text = if let Some(Value::String(chunk_text)) = payload.get("chunk_text") {
-            chunk_text
-        } else {
-            continue;
-        };
-
-        let source_info = if let Some(Value::String(source)) = payload.get("source") {
-            format!(" (from {})", source)
-        } else {
-            String::from(" (from unknown source)")
-        };
+        let source_info = format!(" (from {})", describe_source(payload));
 
         let model_info = if let Some(Value::String(model_name)) = payload.get("model_name") {
             format!(" [model: {}]", model_name)
@@ -59,7 +431,7 @@ async fn get_context(
 
         context_parts.push(format!(
             "Context {} (Relevance: {:.2}){}{}: {}",
-            i + 1,
+            first_number + i,
             score,
             source_info,
             model_info,
@@ -68,30 +440,669 @@ async fn get_context(
     }
 
     let context = context_parts.join("\n\n");
-    info!("{} Found {} relevant context chunks", EMOJI_CONTEXT, results.len());
+    info!(
+        "{} Found {} relevant context chunks",
+        EMOJI_CONTEXT,
+        results.len()
+    );
 
-    Ok((true, Some(context)))
+    Ok(Some(RetrievedContext {
+        text: context,
+        hits: results,
+        first_number,
+    }))
 }
 
-/// Run the interactive chat loop.
-async fn chat_loop(
-    openai_client: &mut OpenAIClient,
+static CITATION: Lazy<Regex> = Lazy::new(|| Regex::new(r"\[(\d+(?:\s*,\s*\d+)*)\]").unwrap());
+
+/// Fenced code blocks (possibly still open at the end of the reply) and inline code spans.
+static CODE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?s)```.*?(?:```|$)|`[^`\n]*`").unwrap());
+
+/// Context numbers cited in a reply as `[1]`, `[2][3]` or `[1, 2]`, limited to `numbers`.
+///
+/// Brackets inside code and indexing such as `a[1]` are not citations.
+fn cited_contexts(reply: &str, numbers: RangeInclusive<usize>) -> BTreeSet<usize> {
+    let prose = CODE.replace_all(reply, " ");
+    CITATION
+        .captures_iter(&prose)
+        .filter(|caps| {
+            let start = caps.get(0).map_or(0, |m| m.start());
+            !prose[..start]
+                .chars()
+                .next_back()
+                .is_some_and(|c| c.is_alphanumeric() || c == '_')
+        })
+        .flat_map(|caps| {
+            caps[1]
+                .split(',')
+                .filter_map(|n| n.trim().parse::<usize>().ok())
+                .collect::<Vec<_>>()
+        })
+        .filter(|n| numbers.contains(n))
+        .collect()
+}
+
+/// Print the sources of a reply: cited contexts normally, uncited ones dimmed.
+fn print_sources(reply: &str, context: &RetrievedContext) {
+    let first = context.first_number;
+    let cited = cited_contexts(reply, first..=first + context.hits.len() - 1);
+
+    println!("\n{}", "Sources:".bold());
+    for (i, hit) in context.hits.iter().enumerate() {
+        let line = format!(
+            "  [{}] {} ({})",
+            first + i,
+            describe_hit(hit),
+            describe_scores(hit, 2)
+        );
+
+        if cited.contains(&(first + i)) {
+            println!("{}", line);
+        } else {
+            println!("{}", line.dimmed());
+        }
+    }
+}
+
+/// Rewrite `source=` values naming a file (e.g. `handbook.md`) to the full source
+/// paths stored in the collection, so files can be picked by name.
+fn expand_source_term(term: &str, known_sources: &[String]) -> String {
+    let (prefix, values) = match source_term_values(term) {
+        Some(values) => (&term[..term.len() - values.len()], values),
+        None => return term.to_string(),
+    };
+
+    let expanded: Vec<String> = values
+        .split(',')
+        .flat_map(|value| {
+            if !is_bare_file_name(value) || known_sources.iter().any(|source| source == value) {
+                return vec![value.to_string()];
+            }
+            let matching: Vec<String> = known_sources
+                .iter()
+                .filter(|source| Path::new(source).ends_with(value))
+                .cloned()
+                .collect();
+            if matching.is_empty() {
+                vec![value.to_string()]
+            } else {
+                matching
+            }
+        })
+        .collect();
+
+    format!("{}{}", prefix, expanded.join(","))
+}
+
+/// The values of a `source=` or `source!=` filter term.
+fn source_term_values(term: &str) -> Option<&str> {
+    match term.split_once("!=").or_else(|| term.split_once('=')) {
+        Some(("source", values)) => Some(values),
+        _ => None,
+    }
+}
+
+/// Whether a `source=` value is a file name without a directory, to be expanded.
+fn is_bare_file_name(value: &str) -> bool {
+    !value.is_empty() && !value.contains(std::path::is_separator)
+}
+
+/// Handle the `/filter` command: show, clear or replace the retrieval filter.
+///
+/// The sources of the collection are listed once per session, the first time a
+/// `source=` value names a bare file that needs expanding, and cached in `known_sources`.
+async fn filter_command(
+    args: &str,
+    filter: &mut Filter,
     qdrant_client: Option<&QdrantService>,
-    top_k: u64,
+    known_sources: &mut Option<Vec<String>>,
+) {
+    match args {
+        "" => {
+            if filter.is_empty() {
+                println!(
+                    "\n{} No filter set. Usage: /filter source=handbook.md [key=value ...] | /filter clear",
+                    EMOJI_SEARCH
+                );
+            } else {
+                println!("\n{} Current filter: {}", EMOJI_SEARCH, filter);
+            }
+        }
+        "clear" | "off" | "none" => {
+            *filter = Filter::new();
+            println!(
+                "\n{} Filter cleared; searching all documents.",
+                EMOJI_SEARCH
+            );
+        }
+        _ => {
+            let terms = split_filter_terms(args);
+            let names_files = terms
+                .iter()
+                .filter_map(|term| source_term_values(term))
+                .any(|values| values.split(',').any(is_bare_file_name));
+            if let (true, None, Some(qdrant)) = (names_files, &known_sources, qdrant_client) {
+                match qdrant.list_sources().await {
+                    Ok(sources) => *known_sources = Some(sources.into_keys().collect()),
+                    Err(e) => warn!("Could not list sources to expand file names: {}", e),
+                }
+            }
+            let sources = known_sources.as_deref().unwrap_or_default();
+            let expression = terms
+                .into_iter()
+                .map(|term| expand_source_term(term, sources))
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            match expression.parse::<Filter>() {
+                Ok(parsed) => {
+                    *filter = parsed;
+                    println!("\n{} Filter set: {}", EMOJI_SEARCH, filter);
+                }
+                Err(e) => println!("\n{} {}", EMOJI_ERROR, e),
+            }
+        }
+    }
+}
+
+/// The latest search of the chat, kept for `/sources` and `/why`.
+struct LastRetrieval {
+    query: String,
+    hits: Vec<SearchHit>,
+    /// Number of the first hit in the prompt.
+    first_number: usize,
+    /// Filter and score threshold the search ran with, so `/why` repeats it
+    /// even after they are changed.
+    filter: Filter,
+    score_threshold: f32,
+    /// Number of candidates `/why` looks at, further down the ranking than the context did.
+    why_limit: u64,
+}
+
+/// Scores of a hit: the search score, and the rerank score on its own scale if reranked.
+fn describe_scores(hit: &SearchHit, precision: usize) -> String {
+    match hit.rerank_score {
+        Some(rerank_score) => format!(
+            "score {:.*}, rerank {:.*}",
+            precision, hit.score, precision, rerank_score
+        ),
+        None => format!("score {:.*}", precision, hit.score),
+    }
+}
+
+/// Location of a hit: its source and chunk index.
+fn describe_hit(hit: &SearchHit) -> String {
+    let mut description = describe_source(&hit.payload);
+    if let Some(index) = hit.payload.get("chunk_index").and_then(|i| i.as_u64()) {
+        description.push_str(&format!(", chunk {}", index));
+    }
+    description
+}
+
+/// Handle the `/sources` command: print everything the last retrieval added to the prompt.
+fn sources_command(last: Option<&LastRetrieval>) {
+    let last = match last {
+        Some(last) => last,
+        None => {
+            println!(
+                "\n{} Nothing retrieved yet; ask a question first.",
+                EMOJI_SEARCH
+            );
+            return;
+        }
+    };
+    if last.hits.is_empty() {
+        println!(
+            "\n{} No context was found for \"{}\". Try /why to see what narrowly missed.",
+            EMOJI_SEARCH, last.query
+        );
+        return;
+    }
+
+    println!(
+        "\n{} {} chunks retrieved for \"{}\":",
+        EMOJI_CONTEXT,
+        last.hits.len(),
+        last.query
+    );
+    for (i, hit) in last.hits.iter().enumerate() {
+        println!(
+            "\n{} {} ({})\n    {}",
+            format!("[{}]", last.first_number + i).bold(),
+            hit.id.to_string().dimmed(),
+            describe_scores(hit, 4),
+            describe_hit(hit)
+        );
+        let text = hit
+            .payload
+            .get("chunk_text")
+            .and_then(|t| t.as_str())
+            .unwrap_or_default();
+        for line in text.lines() {
+            println!("    {}", line);
+        }
+    }
+}
+
+/// Handle the `/why` command: re-run the vector search of the last query with its
+/// filter and a lower score threshold (half the one it used unless given), and
+/// show the cosine score of each candidate, which made it into the context and
+/// which narrowly missed.
+///
+/// Context found only by keyword search in hybrid mode is listed after the candidates.
+async fn why_command(
+    args: &str,
+    last: Option<&LastRetrieval>,
+    embedder: &dyn EmbeddingProvider,
+    qdrant_client: &QdrantService,
+) {
+    let last = match last {
+        Some(last) => last,
+        None => {
+            println!(
+                "\n{} Nothing retrieved yet; ask a question first.",
+                EMOJI_SEARCH
+            );
+            return;
+        }
+    };
+    let threshold = if args.is_empty() {
+        last.score_threshold / 2.0
+    } else {
+        match args.parse::<f32>() {
+            Ok(threshold) => threshold,
+            Err(_) => {
+                println!("\n{} Usage: /why [threshold]", EMOJI_ERROR);
+                return;
+            }
+        }
+    };
+
+    let candidates = match search_last_query(last, embedder, qdrant_client, threshold).await {
+        Ok(candidates) => candidates,
+        Err(e) => {
+            println!("\n{} Search failed: {}", EMOJI_ERROR, e);
+            return;
+        }
+    };
+
+    println!(
+        "\n{} {} candidates for \"{}\" at cosine threshold {:.2} (context used threshold {:.2}):",
+        EMOJI_SEARCH,
+        candidates.len(),
+        last.query,
+        threshold,
+        last.score_threshold
+    );
+    if !last.filter.is_empty() {
+        println!("  Filter: {}", last.filter);
+    }
+    for candidate in &candidates {
+        let used = last.hits.iter().position(|hit| hit.id == candidate.id);
+        let marker = match used {
+            Some(i) => format!("[{}]", last.first_number + i),
+            None => String::from("missed"),
+        };
+        let line = format!(
+            "  {:>6}  {:.4}  {}\n          {}",
+            marker,
+            candidate.score,
+            describe_hit(candidate),
+            preview_hit(candidate)
+        );
+        if used.is_some() {
+            println!("{}", line);
+        } else {
+            println!("{}", line.dimmed());
+        }
+    }
+
+    let keyword_only: Vec<(usize, &SearchHit)> = last
+        .hits
+        .iter()
+        .enumerate()
+        .filter(|(_, hit)| !candidates.iter().any(|candidate| candidate.id == hit.id))
+        .collect();
+    if !keyword_only.is_empty() {
+        println!(
+            "\n  Also in the context, below this threshold or matched by keyword search only:"
+        );
+        for (i, hit) in keyword_only {
+            println!(
+                "  {:>6}  {}\n          {}",
+                format!("[{}]", last.first_number + i),
+                describe_hit(hit),
+                preview_hit(hit)
+            );
+        }
+    }
+}
+
+/// Vector search for the last query with the filter it used.
+async fn search_last_query(
+    last: &LastRetrieval,
+    embedder: &dyn EmbeddingProvider,
+    qdrant_client: &QdrantService,
     score_threshold: f32,
+) -> Result<Vec<SearchHit>> {
+    let q_vec = embedder
+        .embed(std::slice::from_ref(&last.query))
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("No embedding returned for query"))?;
+    qdrant_client
+        .search(
+            q_vec,
+            last.why_limit,
+            score_threshold,
+            Some(&last.filter),
+            false,
+        )
+        .await
+}
+
+/// The start of a hit's text on one line, for listings.
+fn preview_hit(hit: &SearchHit) -> String {
+    hit.payload
+        .get("chunk_text")
+        .and_then(|t| t.as_str())
+        .unwrap_or_default()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(100)
+        .collect()
+}
+
+/// Stream the assistant's reply to the terminal, add it to the conversation and return it.
+async fn stream_reply(
+    chat_session: &mut ChatSession,
+    with_context: bool,
+    temperature: f32,
+) -> Result<String> {
+    let mut stream = chat_session.stream_response(temperature).await?;
+
+    let emoji = if with_context {
+        EMOJI_CONTEXT
+    } else {
+        EMOJI_AI
+    };
+    print!("\n{} ", emoji);
+    io::stdout().flush()?;
+
+    let mut reply = String::new();
+    while let Some(delta) = stream.next().await {
+        let delta = delta?;
+        if with_context {
+            print!("{}", delta.bright_green());
+        } else {
+            print!("{}", delta.bright_cyan());
+        }
+        io::stdout().flush()?;
+        reply.push_str(&delta);
+    }
+    println!();
+
+    chat_session.add_assistant_message(&reply);
+    Ok(reply)
+}
+
+/// Mutable state of the chat REPL, changed by slash commands.
+struct ChatState {
+    qdrant_client: Option<QdrantService>,
+    settings: RetrievalSettings,
+    temperature: f32,
+    last_retrieval: Option<LastRetrieval>,
+    /// Length of the embedder's vectors, measured on the first `/collection` switch.
+    embedding_dimension: Option<usize>,
+    /// Sources of the collection, listed when `/filter` first names a file.
+    known_sources: Option<Vec<String>>,
+}
+
+/// Length of the vectors the embedder actually returns.
+///
+/// The configured dimension is only a guess for self-hosted models, so a probe
+/// string is embedded once and the length cached for the session.
+async fn embedding_dimension(
+    state: &mut ChatState,
+    embedder: &dyn EmbeddingProvider,
+) -> Result<usize> {
+    if let Some(dimension) = state.embedding_dimension {
+        return Ok(dimension);
+    }
+    let dimension = embedder
+        .embed(&["dimension probe".to_string()])
+        .await?
+        .first()
+        .map(|vector| vector.len())
+        .ok_or_else(|| anyhow!("No embedding returned for the probe text"))?;
+    state.embedding_dimension = Some(dimension);
+    Ok(dimension)
+}
+
+/// Handle the `/collection` command: show the current and available collections, or switch.
+///
+/// Switching also enables retrieval in a chat started without context. Collections
+/// whose vector size differs from the embedder's are refused.
+async fn collection_command(args: &str, state: &mut ChatState, embedder: &dyn EmbeddingProvider) {
+    if args.is_empty() {
+        match &state.qdrant_client {
+            Some(qdrant) => {
+                println!(
+                    "\n{} Collection: {}",
+                    EMOJI_SEARCH,
+                    qdrant.collection_name()
+                );
+                match qdrant.list_collections().await {
+                    Ok(collections) => println!("  Available: {}", collections.join(", ")),
+                    Err(e) => println!("\n{} Could not list collections: {}", EMOJI_ERROR, e),
+                }
+            }
+            None => println!(
+                "\n{} Context retrieval is disabled; /collection <name> connects to a collection.",
+                EMOJI_SEARCH
+            ),
+        }
+        return;
+    }
+
+    let client = match QdrantService::new(Some(args.to_string()), None).await {
+        Ok(client) => client,
+        Err(e) => {
+            println!("\n{} {}", EMOJI_ERROR, e);
+            return;
+        }
+    };
+
+    // Query vectors must have the size the collection was embedded with
+    let size = match client.vector_size().await {
+        Ok(size) => size,
+        Err(e) => {
+            println!(
+                "\n{} Could not read the configuration of '{}': {}",
+                EMOJI_ERROR, args, e
+            );
+            return;
+        }
+    };
+    if let Some(size) = size {
+        match embedding_dimension(state, embedder).await {
+            Ok(dimension) if dimension != size => {
+                println!(
+                    "\n{} Collection '{}' stores {}-dimensional vectors, but {} produces {}; not switching.",
+                    EMOJI_ERROR,
+                    args,
+                    size,
+                    embedder.model_name(),
+                    dimension
+                );
+                return;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                println!(
+                    "\n{} Could not embed a probe text to check the vector size: {}",
+                    EMOJI_ERROR, e
+                );
+                return;
+            }
+        }
+    }
+
+    state.qdrant_client = Some(client);
+    state.last_retrieval = None;
+    state.known_sources = None;
+    println!("\n{} Switched to collection: {}", EMOJI_SEARCH, args);
+}
+
+/// Handle the `/save` command: write the conversation to a JSON file.
+fn save_command(args: &str, chat_session: &ChatSession) -> Result<()> {
+    let path = if args.is_empty() {
+        DEFAULT_TRANSCRIPT_PATH
+    } else {
+        args
+    };
+    let transcript = chat_session.transcript();
+    fs::write(path, serde_json::to_string_pretty(&transcript)?)
+        .map_err(|e| anyhow!("Could not write {}: {}", path, e))?;
+    println!(
+        "\n{} Saved {} messages to {}",
+        EMOJI_AI,
+        transcript.messages.len(),
+        path
+    );
+    Ok(())
+}
+
+/// Handle the `/load` command: replace the conversation with a saved one.
+fn load_command(args: &str, chat_session: &mut ChatSession) -> Result<()> {
+    let path = if args.is_empty() {
+        DEFAULT_TRANSCRIPT_PATH
+    } else {
+        args
+    };
+    let content =
+        fs::read_to_string(path).map_err(|e| anyhow!("Could not read {}: {}", path, e))?;
+    let transcript: Transcript = serde_json::from_str(&content)
+        .map_err(|e| anyhow!("{} is not a saved conversation: {}", path, e))?;
+
+    println!(
+        "\n{} Loaded {} messages from {} (saved with {})",
+        EMOJI_AI,
+        transcript.messages.len(),
+        path,
+        transcript.model
+    );
+    chat_session.restore(transcript);
+    Ok(())
+}
+
+/// Run a slash command. Returns `false` when the chat should end.
+async fn run_command(
+    spec: &CommandSpec,
+    args: &str,
+    chat_session: &mut ChatSession,
+    embedder: &dyn EmbeddingProvider,
+    state: &mut ChatState,
+) -> Result<bool> {
+    match spec.name {
+        "/help" => print_help(args),
+        "/exit" => {
+            println!("Goodbye!");
+            return Ok(false);
+        }
+        "/reset" => {
+            chat_session.reset_conversation(true);
+            state.last_retrieval = None;
+            println!("\n{} Conversation history has been reset.", EMOJI_AI);
+        }
+        "/model" => {
+            if !args.is_empty() {
+                chat_session.set_provider(create_chat_provider(Some(args.to_string()))?);
+            }
+            println!("\n{} Model: {}", EMOJI_AI, chat_session.model_name());
+        }
+        "/topk" => {
+            match parse_arg::<u64>(spec, args)? {
+                Some(0) => return Err(anyhow!("/topk must be at least 1")),
+                Some(top_k) => state.settings.top_k = top_k,
+                None => {}
+            }
+            println!(
+                "\n{} Chunks per prompt: {}",
+                EMOJI_SEARCH, state.settings.top_k
+            );
+        }
+        "/threshold" => {
+            if let Some(threshold) = parse_arg::<f32>(spec, args)? {
+                state.settings.score_threshold = threshold;
+            }
+            println!(
+                "\n{} Score threshold: {:.2}",
+                EMOJI_SEARCH, state.settings.score_threshold
+            );
+        }
+        "/temperature" => {
+            match parse_arg::<f32>(spec, args)? {
+                Some(temperature) if !(0.0..=2.0).contains(&temperature) => {
+                    return Err(anyhow!("/temperature must be between 0 and 2"));
+                }
+                Some(temperature) => state.temperature = temperature,
+                None => {}
+            }
+            println!("\n{} Temperature: {:.2}", EMOJI_AI, state.temperature);
+        }
+        "/collection" => collection_command(args, state, embedder).await,
+        "/filter" => {
+            filter_command(
+                args,
+                &mut state.settings.filter,
+                state.qdrant_client.as_ref(),
+                &mut state.known_sources,
+            )
+            .await
+        }
+        "/sources" => sources_command(state.last_retrieval.as_ref()),
+        "/why" => match &state.qdrant_client {
+            Some(qdrant) => {
+                why_command(args, state.last_retrieval.as_ref(), embedder, qdrant).await
+            }
+            None => println!("\n{} Context retrieval is disabled.", EMOJI_ERROR),
+        },
+        "/save" => save_command(args, chat_session)?,
+        "/load" => load_command(args, chat_session)?,
+        other => return Err(anyhow!("Command {} is not implemented", other)),
+    }
+    Ok(true)
+}
+
+/// Run the interactive chat loop.
+async fn chat_loop(
+    chat_session: &mut ChatSession,
+    embedder: &dyn EmbeddingProvider,
+    mut state: ChatState,
 ) -> Result<()> {
-    println!("\nChat with OpenAI (type 'exit' to quit, 'reset' to clear conversation history):");
+    println!(
+        "\nChat with {} (type /help for commands, /exit to quit):",
+        chat_session.model_name()
+    );
 
-    if qdrant_client.is_some() {
+    if state.qdrant_client.is_some() {
         println!(
             "\n{} = Using saved context | {} = AI knowledge | {} = Searching",
             EMOJI_CONTEXT, EMOJI_AI, EMOJI_SEARCH
         );
     } else {
-        println!("\n{} = AI knowledge (no context retrieval enabled)", EMOJI_AI);
+        println!(
+            "\n{} = AI knowledge (no context retrieval enabled)",
+            EMOJI_AI
+        );
     }
 
-    let mut rl = DefaultEditor::new()?;
+    let mut rl: Editor<ChatHelper, DefaultHistory> = Editor::new()?;
+    rl.set_helper(Some(ChatHelper::new()));
 
     loop {
         // Get user query
@@ -107,68 +1118,98 @@ async fn chat_loop(
             }
         };
 
-        // Check for special commands
-        match query.to_lowercase().as_str() {
-            "exit" | "quit" | "bye" => {
-                println!("Goodbye!");
-                break;
+        // Slash commands; anything else is a message for the model
+        if let Some((spec, args)) = parse_command(&query) {
+            match run_command(spec, args, chat_session, embedder, &mut state).await {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => println!("\n{} {}", EMOJI_ERROR, e),
             }
-            "reset" => {
-                openai_client.reset_conversation(true);
-                println!("\n{} Conversation history has been reset.", EMOJI_AI);
-                continue;
-            }
-            _ => {}
+            continue;
         }
 
         // Add user query to conversation
-        openai_client.add_user_message(&query);
+        chat_session.add_user_message(&query);
 
         // Try to find relevant context if available
-        if let Some(qdrant) = qdrant_client {
-            match get_context(&query, openai_client, qdrant, top_k, score_threshold).await {
-                Ok((context_found, Some(context))) if context_found => {
-                    // Add context to chat as system message
-                    openai_client.add_system_message(&format!(
+        if let Some(qdrant) = &state.qdrant_client {
+            // Resolve follow-ups against the conversation before searching
+            let search_query = if state.settings.rewrite_query {
+                match chat_session.condense_query().await {
+                    Ok(rewritten) => rewritten,
+                    Err(e) => {
+                        warn!(
+                            "Query rewriting failed, searching for the message as typed: {}",
+                            e
+                        );
+                        query.clone()
+                    }
+                }
+            } else {
+                query.clone()
+            };
+            if search_query != query {
+                debug!("Rewrote search query: {:?} -> {:?}", query, search_query);
+            }
+
+            let first_number = chat_session.next_context_number();
+            let context = get_context(
+                &search_query,
+                embedder,
+                qdrant,
+                &state.settings,
+                first_number,
+            )
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Error retrieving context: {}", e);
+                None
+            });
+            state.last_retrieval = Some(LastRetrieval {
+                query: search_query,
+                hits: context
+                    .as_ref()
+                    .map(|context| context.hits.clone())
+                    .unwrap_or_default(),
+                first_number,
+                filter: state.settings.filter.clone(),
+                score_threshold: state.settings.score_threshold,
+                why_limit: state.settings.fetch_k.max(state.settings.top_k * 2),
+            });
+
+            match context {
+                Some(context) => {
+                    // Attach context to this turn only, rather than the persistent history
+                    let prompt = format!(
                         "Here is some relevant context to help answer the question. \
-                        Use this information if it's helpful for answering the question:\n{}",
-                        context
-                    ));
-                    
-                    // Get response with context
-                    match openai_client.get_response(0.7).await {
-                        Ok(response) => {
-                            println!("\n{} {}", EMOJI_CONTEXT, response.bright_green());
-                        }
+                        Use this information if it's helpful for answering the question, \
+                        and cite the contexts you use by number in square brackets, e.g. [{}]:\n{}",
+                        first_number, context.text
+                    );
+                    chat_session.attach_context(&prompt, first_number + context.hits.len() - 1);
+
+                    // Get response with context, then list the sources it drew on
+                    match stream_reply(chat_session, true, state.temperature).await {
+                        Ok(reply) => print_sources(&reply, &context),
                         Err(e) => {
                             error!("Error getting response: {}", e);
                             println!("\n{} Error getting response", EMOJI_ERROR);
                         }
                     }
                 }
-                _ => {
+                None => {
                     // Get response without context
-                    match openai_client.get_response(0.7).await {
-                        Ok(response) => {
-                            println!("\n{} {}", EMOJI_AI, response.bright_cyan());
-                        }
-                        Err(e) => {
-                            error!("Error getting response: {}", e);
-                            println!("\n{} Error getting response", EMOJI_ERROR);
-                        }
+                    if let Err(e) = stream_reply(chat_session, false, state.temperature).await {
+                        error!("Error getting response: {}", e);
+                        println!("\n{} Error getting response", EMOJI_ERROR);
                     }
                 }
             }
         } else {
             // No context retrieval, just get response
-            match openai_client.get_response(0.7).await {
-                Ok(response) => {
-                    println!("\n{} {}", EMOJI_AI, response.bright_cyan());
-                }
-                Err(e) => {
-                    error!("Error getting response: {}", e);
-                    println!("\n{} Error getting response", EMOJI_ERROR);
-                }
+            if let Err(e) = stream_reply(chat_session, false, state.temperature).await {
+                error!("Error getting response: {}", e);
+                println!("\n{} Error getting response", EMOJI_ERROR);
             }
         }
     }
@@ -184,15 +1225,16 @@ pub async fn run_chat(no_context: bool) -> Result<()> {
         return Err(anyhow!("Environment validation failed"));
     }
 
-    // Initialize OpenAI client
-    let mut openai_client = OpenAIClient::new(
-        None,
-        Some(DEFAULT_CHAT_MODEL.clone()),
-        Some(DEFAULT_EMBEDDING_MODEL.clone()),
-    )?;
+    // Initialize the configured chat provider
+    let chat_provider = create_chat_provider(None)?;
+    let mut chat_session = ChatSession::new(chat_provider);
+    chat_session.set_history_strategy(HISTORY_STRATEGY.parse()?);
+
+    // Initialize the configured embedding provider used for retrieval
+    let embedder = create_embedding_provider(Some(DEFAULT_EMBEDDING_MODEL.clone()))?;
 
     // Add system message
-    openai_client.add_system_message(
+    chat_session.add_system_message(
         "You are a helpful assistant that can answer questions based on provided context or general knowledge. \
         If context is provided, prioritize that information in your answers. \
         If no context is provided or the question is outside the scope of the context, \
@@ -217,14 +1259,249 @@ pub async fn run_chat(no_context: bool) -> Result<()> {
         None
     };
 
+    let settings = RetrievalSettings {
+        top_k: 3,
+        score_threshold: 0.3,
+        filter: Filter::new(),
+        reranker: create_reranker()?,
+        mmr_lambda: *MMR_LAMBDA,
+        fetch_k: *RERANK_FETCH_K,
+        neighbours: *CONTEXT_NEIGHBOURS,
+        rewrite_query: *QUERY_REWRITE,
+    };
+    if let Some(reranker) = &settings.reranker {
+        info!(
+            "Reranking the top {} candidates with {}",
+            settings.fetch_k,
+            reranker.name()
+        );
+    }
+    if let Some(lambda) = settings.mmr_lambda {
+        info!(
+            "Selecting diverse context from the top {} candidates (MMR lambda {:.2})",
+            settings.fetch_k, lambda
+        );
+    }
+
+    let state = ChatState {
+        qdrant_client,
+        settings,
+        temperature: 0.7,
+        last_retrieval: None,
+        embedding_dimension: None,
+        known_sources: None,
+    };
+
     // Start chat loop
-    chat_loop(
-        &mut openai_client,
-        qdrant_client.as_ref(),
-        3,  // top_k
-        0.3, // score_threshold
-    )
-    .await?;
+    chat_loop(&mut chat_session, embedder.as_ref(), state).await?;
 
     Ok(())
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::LexicalReranker;
+    use crate::services::qdrant_service::PointId;
+    use async_trait::async_trait;
+
+    fn hit(id: u64, score: f32, text: &str) -> SearchHit {
+        SearchHit {
+            id: PointId::Num(id),
+            score,
+            payload: HashMap::from([("chunk_text".to_string(), Value::from(text))]),
+            vector: None,
+            rerank_score: None,
+        }
+    }
+
+    fn ids(hits: &[SearchHit]) -> Vec<PointId> {
+        hits.iter().map(|hit| hit.id.clone()).collect()
+    }
+
+    fn with_vector(mut hit: SearchHit, vector: &[f32]) -> SearchHit {
+        hit.vector = Some(vector.to_vec());
+        hit
+    }
+
+    struct FailingReranker;
+
+    #[async_trait]
+    impl Reranker for FailingReranker {
+        async fn score(&self, _query: &str, _documents: &[String]) -> Result<Vec<f32>> {
+            Err(anyhow!("service unavailable"))
+        }
+
+        fn name(&self) -> &str {
+            "failing"
+        }
+    }
+
+    #[tokio::test]
+    async fn rerank_hits_sorts_by_reranker_scores() {
+        let hits = vec![
+            hit(1, 0.9, "Lunch menu for Tuesday"),
+            hit(2, 0.8, "Restart the deploy pipeline"),
+            hit(3, 0.7, "Restart the pipeline"),
+        ];
+        let reranked = rerank_hits(&LexicalReranker, "restart deploy pipeline", hits).await;
+
+        assert_eq!(
+            ids(&reranked),
+            [PointId::Num(2), PointId::Num(3), PointId::Num(1)]
+        );
+        // The search score is kept next to the rerank score
+        assert_eq!(reranked[0].rerank_score, Some(1.0));
+        assert_eq!(reranked[0].score, 0.8);
+        assert_eq!(describe_scores(&reranked[0], 2), "score 0.80, rerank 1.00");
+    }
+
+    #[tokio::test]
+    async fn rerank_hits_keeps_search_order_when_reranker_fails() {
+        let hits = vec![hit(1, 0.9, "a"), hit(2, 0.8, "b"), hit(3, 0.7, "c")];
+        let reranked = rerank_hits(&FailingReranker, "query", hits).await;
+
+        assert_eq!(
+            ids(&reranked),
+            [PointId::Num(1), PointId::Num(2), PointId::Num(3)]
+        );
+        assert_eq!(reranked[0].score, 0.9);
+        assert_eq!(reranked[0].rerank_score, None);
+    }
+
+    #[test]
+    fn mmr_with_lambda_one_keeps_relevance_order() {
+        let hits = vec![
+            with_vector(hit(1, 0.9, "a"), &[1.0, 0.0]),
+            with_vector(hit(2, 0.8, "a again"), &[1.0, 0.0]),
+            with_vector(hit(3, 0.5, "b"), &[0.0, 1.0]),
+        ];
+        let selected = select_mmr(hits, 3, 1.0);
+        assert_eq!(
+            ids(&selected),
+            [PointId::Num(1), PointId::Num(2), PointId::Num(3)]
+        );
+    }
+
+    #[test]
+    fn mmr_with_low_lambda_skips_near_duplicates() {
+        let hits = vec![
+            with_vector(hit(1, 0.9, "a"), &[1.0, 0.0]),
+            with_vector(hit(2, 0.89, "a again"), &[0.99, 0.01]),
+            with_vector(hit(3, 0.6, "b"), &[0.0, 1.0]),
+        ];
+        let selected = select_mmr(hits, 2, 0.3);
+        assert_eq!(ids(&selected), [PointId::Num(1), PointId::Num(3)]);
+    }
+
+    #[test]
+    fn mmr_never_treats_hits_without_vectors_as_redundant() {
+        let hits = vec![
+            with_vector(hit(1, 0.9, "a"), &[1.0, 0.0]),
+            with_vector(hit(2, 0.85, "a again"), &[1.0, 0.0]),
+            hit(3, 0.8, "unknown"),
+        ];
+        let selected = select_mmr(hits, 2, 0.5);
+        assert_eq!(ids(&selected), [PointId::Num(1), PointId::Num(3)]);
+    }
+
+    #[test]
+    fn mmr_returns_every_hit_when_top_k_exceeds_them() {
+        let hits = vec![hit(1, 0.9, "a"), hit(2, 0.8, "b")];
+        assert_eq!(select_mmr(hits, 10, 0.5).len(), 2);
+        assert!(select_mmr(Vec::new(), 3, 0.5).is_empty());
+    }
+
+    #[test]
+    fn append_chunk_merges_repeated_overlap() {
+        let mut passage =
+            String::from("The pipeline deploys every Tuesday. Rollbacks need approval.");
+        append_chunk(
+            &mut passage,
+            "Rollbacks need approval. Ask the on-call engineer.",
+        );
+        assert_eq!(
+            passage,
+            "The pipeline deploys every Tuesday. Rollbacks need approval. Ask the on-call engineer."
+        );
+    }
+
+    #[test]
+    fn append_chunk_joins_unrelated_chunks_with_a_newline() {
+        let mut passage = String::new();
+        append_chunk(&mut passage, "First chunk.");
+        append_chunk(&mut passage, "Second chunk.");
+        assert_eq!(passage, "First chunk.\nSecond chunk.");
+    }
+
+    #[test]
+    fn append_chunk_ignores_short_or_mid_word_overlap() {
+        // Shared text shorter than the minimum overlap is kept twice
+        let mut passage = String::from("Run it now.");
+        append_chunk(&mut passage, "now. Then wait.");
+        assert_eq!(passage, "Run it now.\nnow. Then wait.");
+
+        // An overlap must start at a word boundary of the passage
+        let mut passage = String::from("The reconfiguration of the cluster");
+        append_chunk(&mut passage, "configuration of the cluster is done");
+        assert_eq!(
+            passage,
+            "The reconfiguration of the cluster\nconfiguration of the cluster is done"
+        );
+    }
+
+    #[test]
+    fn cited_contexts_reads_single_repeated_and_listed_markers() {
+        let reply = "Deploys run on Tuesday [1][3], rollbacks need approval [2, 4].";
+        assert_eq!(cited_contexts(reply, 1..=4), BTreeSet::from([1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn cited_contexts_ignores_numbers_outside_the_block() {
+        let reply = "See [1], [4] and [7].";
+        assert_eq!(cited_contexts(reply, 4..=6), BTreeSet::from([4]));
+        assert!(cited_contexts(reply, 2..=3).is_empty());
+    }
+
+    #[test]
+    fn cited_contexts_ignores_brackets_in_code_and_indexing() {
+        let reply =
+            "Use `items[2]` or values[3] as in [1]:\n```rust\nlet x = a[4];\n```\nand `b [5]`.";
+        assert_eq!(cited_contexts(reply, 1..=5), BTreeSet::from([1]));
+
+        // A fence still open at the end of the reply hides its brackets too
+        let reply = "Per [2]:\n```\nlet y = [3];";
+        assert_eq!(cited_contexts(reply, 1..=3), BTreeSet::from([2]));
+    }
+
+    #[test]
+    fn source_terms_expand_bare_file_names_only() {
+        let known = vec![
+            "docs/handbook.md".to_string(),
+            "notes/handbook.md".to_string(),
+            "docs/faq.md".to_string(),
+        ];
+
+        assert_eq!(
+            expand_source_term("source=handbook.md", &known),
+            "source=docs/handbook.md,notes/handbook.md"
+        );
+        assert_eq!(
+            expand_source_term("source!=faq.md,other.md", &known),
+            "source!=docs/faq.md,other.md"
+        );
+        // Paths are used as given
+        assert_eq!(
+            expand_source_term("source=docs/handbook.md", &known),
+            "source=docs/handbook.md"
+        );
+        assert_eq!(
+            expand_source_term("tags=handbook.md", &known),
+            "tags=handbook.md"
+        );
+
+        assert!(is_bare_file_name("handbook.md"));
+        assert!(!is_bare_file_name("docs/handbook.md"));
+        assert!(!is_bare_file_name(""));
+    }
+}
@@ -0,0 +1,293 @@
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+use std::borrow::Cow;
+use std::str::FromStr;
+
+/// A slash command of the chat REPL.
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    /// Argument synopsis shown in help and hints.
+    pub usage: &'static str,
+    pub help: &'static str,
+    /// Fixed argument values offered by tab completion.
+    pub completions: &'static [&'static str],
+    /// Whether the argument is a file path, completed from the file system.
+    pub takes_path: bool,
+}
+
+impl CommandSpec {
+    const fn new(name: &'static str, usage: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            aliases: &[],
+            usage,
+            help,
+            completions: &[],
+            takes_path: false,
+        }
+    }
+
+    const fn aliases(mut self, aliases: &'static [&'static str]) -> Self {
+        self.aliases = aliases;
+        self
+    }
+
+    const fn completions(mut self, completions: &'static [&'static str]) -> Self {
+        self.completions = completions;
+        self
+    }
+
+    const fn takes_path(mut self) -> Self {
+        self.takes_path = true;
+        self
+    }
+
+    /// Whether `name` (with its leading slash) invokes this command.
+    fn matches(&self, name: &str) -> bool {
+        self.name == name || self.aliases.contains(&name)
+    }
+}
+
+/// All chat commands, in the order they are listed by `/help`.
+pub static COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("/help", "[command]", "List commands, or describe one"),
+    CommandSpec::new("/exit", "", "Leave the chat").aliases(&["/quit", "/bye"]),
+    CommandSpec::new("/reset", "", "Clear the conversation history, keeping the system prompt"),
+    CommandSpec::new("/model", "[name]", "Show or switch the chat model, keeping the conversation"),
+    CommandSpec::new("/topk", "[n]", "Show or set the number of chunks added to the prompt"),
+    CommandSpec::new("/threshold", "[score]", "Show or set the minimum relevance score of retrieved chunks"),
+    CommandSpec::new("/temperature", "[0-2]", "Show or set the sampling temperature of replies"),
+    CommandSpec::new("/collection", "[name]", "Show the Qdrant collection and the available ones, or switch to another"),
+    CommandSpec::new("/filter", "[key=value ...|clear]", "Show, set or clear the payload filter scoping retrieval")
+        .completions(&["clear", "source=", "tags="]),
+    CommandSpec::new("/sources", "", "Show everything the last retrieval added to the prompt"),
+    CommandSpec::new("/why", "[threshold]", "Re-run the last search with a lower threshold to show near misses"),
+    CommandSpec::new("/save", "[path]", "Save the conversation to a JSON file (default: chat-history.json)").takes_path(),
+    CommandSpec::new("/load", "[path]", "Replace the conversation with one saved by /save").takes_path(),
+];
+
+/// Default file for `/save` and `/load`.
+pub const DEFAULT_TRANSCRIPT_PATH: &str = "chat-history.json";
+
+/// Look up a command by name or alias, with its leading slash.
+pub fn find_command(name: &str) -> Option<&'static CommandSpec> {
+    let name = name.to_lowercase();
+    COMMANDS.iter().find(|spec| spec.matches(&name))
+}
+
+/// Split an input line into a command and its (trimmed) arguments.
+///
+/// Only lines whose first word is a known command or alias are commands; anything
+/// else, including a path such as "/etc/hosts isn't resolving", is a message and
+/// yields `None`.
+pub fn parse_command(line: &str) -> Option<(&'static CommandSpec, &str)> {
+    let line = line.trim();
+    if !line.starts_with('/') {
+        return None;
+    }
+
+    let (name, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    find_command(name).map(|spec| (spec, args.trim()))
+}
+
+/// Parse the optional argument of a "show or set" command.
+///
+/// An empty argument yields `None` (show the current value).
+pub fn parse_arg<T: FromStr>(spec: &CommandSpec, args: &str) -> Result<Option<T>> {
+    if args.is_empty() {
+        return Ok(None);
+    }
+    args.parse()
+        .map(Some)
+        .map_err(|_| anyhow!("Invalid argument '{}'. Usage: {} {}", args, spec.name, spec.usage))
+}
+
+/// Print the list of commands, or the help of one command.
+pub fn print_help(args: &str) {
+    if !args.is_empty() {
+        let name = if args.starts_with('/') { args.to_string() } else { format!("/{}", args) };
+        match find_command(&name) {
+            Some(spec) => {
+                println!("\n{} {}", spec.name.bold(), spec.usage);
+                println!("  {}", spec.help);
+                if !spec.aliases.is_empty() {
+                    println!("  Aliases: {}", spec.aliases.join(", "));
+                }
+            }
+            None => println!("\nUnknown command '{}'. Type /help to list commands.", args),
+        }
+        return;
+    }
+
+    println!("\nCommands (Tab completes):");
+    for spec in COMMANDS {
+        let synopsis = format!("{} {}", spec.name, spec.usage);
+        println!("  {:<36} {}", synopsis.trim_end().bold(), spec.help);
+    }
+}
+
+/// Line editor helper completing command names and arguments, and hinting
+/// the usage of a command once its name is typed.
+pub struct ChatHelper {
+    filenames: FilenameCompleter,
+}
+
+impl ChatHelper {
+    pub fn new() -> Self {
+        Self {
+            filenames: FilenameCompleter::new(),
+        }
+    }
+}
+
+impl Default for ChatHelper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Completer for ChatHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let typed = &line[..pos];
+        if !typed.starts_with('/') {
+            return Ok((0, Vec::new()));
+        }
+
+        // Still typing the command name
+        let name_end = match typed.find(char::is_whitespace) {
+            Some(end) => end,
+            None => {
+                let candidates = COMMANDS
+                    .iter()
+                    .flat_map(|spec| std::iter::once(&spec.name).chain(spec.aliases))
+                    .filter(|name| name.starts_with(typed))
+                    .map(|name| Pair {
+                        display: name.to_string(),
+                        replacement: format!("{} ", name),
+                    })
+                    .collect();
+                return Ok((0, candidates));
+            }
+        };
+
+        let spec = match find_command(&typed[..name_end]) {
+            Some(spec) => spec,
+            None => return Ok((0, Vec::new())),
+        };
+        if spec.takes_path {
+            return self.filenames.complete(line, pos, ctx);
+        }
+
+        let word_start = typed.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let word = &typed[word_start..];
+        let values: Vec<&str> = if spec.name == "/help" {
+            COMMANDS.iter().map(|spec| spec.name).collect()
+        } else {
+            spec.completions.to_vec()
+        };
+        let candidates = values
+            .into_iter()
+            .filter(|value| value.starts_with(word))
+            .map(|value| Pair {
+                display: value.to_string(),
+                replacement: value.to_string(),
+            })
+            .collect();
+        Ok((word_start, candidates))
+    }
+}
+
+impl Hinter for ChatHelper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<String> {
+        if pos < line.len() || !line.starts_with('/') {
+            return None;
+        }
+        let name = line.trim_end();
+        let spec = find_command(name)?;
+        if spec.usage.is_empty() || line.len() > name.len() + 1 {
+            return None;
+        }
+        let separator = if line.len() == name.len() { " " } else { "" };
+        Some(format!("{}{}", separator, spec.usage))
+    }
+}
+
+impl Highlighter for ChatHelper {
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(hint.dimmed().to_string())
+    }
+}
+
+impl Validator for ChatHelper {}
+
+impl Helper for ChatHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustyline::history::DefaultHistory;
+
+    fn complete(line: &str) -> (usize, Vec<String>) {
+        let history = DefaultHistory::new();
+        let (start, candidates) = ChatHelper::new()
+            .complete(line, line.len(), &Context::new(&history))
+            .unwrap();
+        (start, candidates.into_iter().map(|pair| pair.replacement).collect())
+    }
+
+    #[test]
+    fn parse_command_splits_name_and_arguments() {
+        let (spec, args) = parse_command("  /topk   5 ").unwrap();
+        assert_eq!(spec.name, "/topk");
+        assert_eq!(args, "5");
+
+        let (spec, args) = parse_command("/QUIT").unwrap();
+        assert_eq!(spec.name, "/exit");
+        assert_eq!(args, "");
+    }
+
+    #[test]
+    fn parse_command_sends_other_lines_as_messages() {
+        assert!(parse_command("reset").is_none());
+        assert!(parse_command("/etc/hosts isn't resolving, why?").is_none());
+        assert!(parse_command("/topk5").is_none());
+        assert!(parse_command("/").is_none());
+    }
+
+    #[test]
+    fn parse_arg_shows_on_empty_and_reports_usage_on_errors() {
+        let spec = find_command("/topk").unwrap();
+        assert_eq!(parse_arg::<u64>(spec, "").unwrap(), None);
+        assert_eq!(parse_arg::<u64>(spec, "7").unwrap(), Some(7));
+
+        let error = parse_arg::<u64>(spec, "seven").unwrap_err().to_string();
+        assert_eq!(error, "Invalid argument 'seven'. Usage: /topk [n]");
+    }
+
+    #[test]
+    fn completer_offers_command_names_and_aliases() {
+        let (start, names) = complete("/t");
+        assert_eq!(start, 0);
+        assert_eq!(names, ["/topk ", "/threshold ", "/temperature "]);
+        assert_eq!(complete("/b"), (0, vec!["/bye ".to_string()]));
+        assert_eq!(complete("hello /t"), (0, Vec::new()));
+    }
+
+    #[test]
+    fn completer_offers_argument_values() {
+        assert_eq!(complete("/filter cl"), (8, vec!["clear".to_string()]));
+        assert_eq!(complete("/filter source=a.md t"), (20, vec!["tags=".to_string()]));
+        assert_eq!(complete("/help /s"), (6, vec!["/sources".to_string(), "/save".to_string()]));
+        assert_eq!(complete("/nope x"), (0, Vec::new()));
+    }
+}
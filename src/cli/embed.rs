@@ -1,36 +1,160 @@
 use anyhow::{anyhow, Result};
-use log::{error, info};
-use serde_json::Value;
-use std::io::{self, BufRead};
+use clap::{Args, ValueEnum};
+use log::{error, info, warn};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Read};
+use std::env;
+use std::path::{Path, PathBuf};
 
-use crate::clients::OpenAIClient;
+use crate::clients::{create_embedding_provider, EmbeddingProvider};
 use crate::config::{
-    DEFAULT_EMBEDDING_MODEL, DEFAULT_MAX_SENTENCES_PER_CHUNK, QDRANT_COLLECTION,
+    DEFAULT_EMBEDDING_MODEL, DEFAULT_MAX_CHARS_PER_SECTION_CHUNK, DEFAULT_MAX_SENTENCES_PER_CHUNK,
+    DEFAULT_MAX_TOKENS_PER_CHUNK, DEFAULT_OVERLAP_TOKENS_PER_CHUNK, QDRANT_COLLECTION,
     validate_environment,
 };
-use crate::services::chunker::{chunk_text, list_text_files, read_file_content};
-use crate::services::qdrant_service::QdrantService;
+use crate::services::chunker::{
+    chunk_parts, chunk_text, file_mtime, list_text_files, process_file, walk_text_files,
+    Chunker, MarkdownChunker, RecordChunker, SentenceChunker, TokenChunker,
+};
+use crate::services::loader::{record_field_key, DocumentPart, RecordTemplate};
+use crate::services::qdrant_service::{content_hash, PointId, QdrantService, SourceState};
+use crate::services::sparse::Bm25Encoder;
+
+/// How text is split into chunks before embedding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ChunkStrategy {
+    /// Fixed number of sentences per chunk
+    Sentence,
+    /// Token budget per chunk with overlap between consecutive chunks
+    Token,
+    /// Markdown sections split on headings, keeping code blocks intact
+    Markdown,
+}
+
+/// Format of input piped on stdin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum InputFormat {
+    /// Plain text, chunked like a file
+    Text,
+    /// One JSON object per line: {"id", "text", "metadata"}
+    Jsonl,
+}
+
+/// Arguments of the embed command.
+#[derive(Debug, Args)]
+pub struct EmbedArgs {
+    /// Path to text file to embed
+    #[clap(short, long)]
+    pub file: Option<String>,
+
+    /// Text to embed directly
+    #[clap(short, long)]
+    pub text: Option<String>,
+
+    /// Read the text to embed from stdin without prompting
+    #[clap(long, conflicts_with_all = ["file", "dir", "text"])]
+    pub stdin: bool,
+
+    /// Source name stored with text read from stdin
+    #[clap(long, default_value = "stdin")]
+    pub source_name: String,
+
+    /// Format of the input read from stdin
+    // clap waives `requires` when another input conflicting with --stdin is given,
+    // so those conflicts are repeated here
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = InputFormat::Text,
+        requires = "stdin",
+        conflicts_with_all = ["file", "dir", "text"]
+    )]
+    pub format: InputFormat,
+
+    /// Directory to embed recursively (honours .gitignore)
+    #[clap(short, long)]
+    pub dir: Option<String>,
+
+    /// Only embed files matching this glob, relative to --dir (repeatable)
+    #[clap(long)]
+    pub include: Vec<String>,
+
+    /// Skip files matching this glob, relative to --dir (repeatable)
+    #[clap(long)]
+    pub exclude: Vec<String>,
+
+    /// List available text files
+    #[clap(short = 'l', long)]
+    pub list_files: bool,
+
+    /// Re-embed files even if they are unchanged since the last run (e.g. after changing chunking options)
+    #[clap(long)]
+    pub force: bool,
+
+    /// Chunking strategy for every text file (default: markdown for .md files, sentence otherwise)
+    #[clap(long, value_enum)]
+    pub chunker: Option<ChunkStrategy>,
+
+    /// Maximum sentences per chunk (sentence chunker)
+    #[clap(long, default_value_t = DEFAULT_MAX_SENTENCES_PER_CHUNK)]
+    pub max_sentences: usize,
+
+    /// Maximum tokens per chunk (token chunker)
+    #[clap(long, default_value_t = DEFAULT_MAX_TOKENS_PER_CHUNK)]
+    pub max_tokens: usize,
+
+    /// Tokens shared between consecutive chunks (token chunker)
+    #[clap(long, default_value_t = DEFAULT_OVERLAP_TOKENS_PER_CHUNK)]
+    pub overlap_tokens: usize,
+
+    /// Maximum characters per chunk (markdown chunker)
+    #[clap(long, default_value_t = DEFAULT_MAX_CHARS_PER_SECTION_CHUNK)]
+    pub max_chars: usize,
+
+    /// CSV columns or JSON fields embedded as text, comma-separated (default: all);
+    /// the other fields are stored as payload metadata
+    #[clap(long, value_delimiter = ',')]
+    pub text_fields: Vec<String>,
+
+    /// Text template for CSV rows and JSON records, e.g. "{name}: {description}"
+    #[clap(long)]
+    pub template: Option<String>,
+}
 
-/// Get input text from file or direct input.
+/// Build the chunker selected on the command line, sentences by default.
+fn build_chunker(args: &EmbedArgs, model_name: &str) -> Result<Box<dyn Chunker>> {
+    match args.chunker.unwrap_or(ChunkStrategy::Sentence) {
+        ChunkStrategy::Sentence => Ok(Box::new(SentenceChunker::new(args.max_sentences))),
+        ChunkStrategy::Token => Ok(Box::new(TokenChunker::new(
+            model_name,
+            args.max_tokens,
+            args.overlap_tokens,
+        )?)),
+        ChunkStrategy::Markdown => Ok(Box::new(MarkdownChunker::new(args.max_chars))),
+    }
+}
+
+/// Input selected for embedding when no file or directory is given.
+enum Input {
+    /// A file picked from the listing, embedded like `--file`.
+    File(String),
+    /// Text typed or passed on the command line.
+    Text { text: String, source: String },
+}
+
+/// Get input text from direct input or an interactive prompt.
 async fn get_input_text(
-    file_path: Option<String>,
     text_input: Option<String>,
     list_files: bool,
-) -> Result<Option<(String, String)>> {
-    // Check for file input
-    if let Some(path) = file_path {
-        match read_file_content(&path) {
-            Ok(content) => return Ok(Some((content, path))),
-            Err(e) => {
-                error!("Could not read file: {}: {}", path, e);
-                return Ok(None);
-            }
-        }
-    }
-
+) -> Result<Option<Input>> {
     // Check for direct text input
     if let Some(text) = text_input {
-        return Ok(Some((text, "command_line_input".to_string())));
+        return Ok(Some(Input::Text {
+            text,
+            source: "command_line_input".to_string(),
+        }));
     }
 
     // If no input provided, prompt user
@@ -50,10 +174,7 @@ async fn get_input_text(
 
             if let Ok(idx) = input.trim().parse::<usize>() {
                 if idx > 0 && idx <= files.len() {
-                    match read_file_content(&files[idx - 1]) {
-                        Ok(content) => return Ok(Some((content, files[idx - 1].clone()))),
-                        Err(e) => error!("Could not read file: {}", e),
-                    }
+                    return Ok(Some(Input::File(files[idx - 1].clone())));
                 } else {
                     error!("Invalid selection");
                 }
@@ -70,26 +191,27 @@ async fn get_input_text(
         }
 
         if !text.trim().is_empty() {
-            return Ok(Some((text, "manual_input".to_string())));
+            return Ok(Some(Input::Text {
+                text,
+                source: "manual_input".to_string(),
+            }));
         }
     }
 
     Ok(None)
 }
 
-/// Embed text chunks and store in vector database.
-async fn embed_text(
-    text: &str,
-    source_name: &str,
-    model_name: &str,
+/// Embed prepared chunks and store them in the vector database.
+///
+/// Returns the ids of the stored points, which are empty when there was nothing to embed.
+async fn embed_chunks(
+    mut chunks_data: Vec<HashMap<String, Value>>,
+    embedder: &dyn EmbeddingProvider,
     collection_name: &str,
-    max_sentences: usize,
-) -> Result<bool> {
-    // Initialize OpenAI client
-    let openai_client = OpenAIClient::new(None, None, Some(model_name.to_string()))?;
-
-    // Process text into chunks
-    let chunks_data = chunk_text(text, max_sentences, source_name);
+) -> Result<Vec<PointId>> {
+    for item in chunks_data.iter_mut() {
+        item.insert("model_name".to_string(), Value::String(embedder.model_name().to_string()));
+    }
     let chunks: Vec<String> = chunks_data
         .iter()
         .filter_map(|item| {
@@ -103,13 +225,13 @@ async fn embed_text(
 
     if chunks.is_empty() {
         error!("No chunks generated from text");
-        return Ok(false);
+        return Ok(Vec::new());
     }
 
     info!("Text chunked into {} segments", chunks.len());
     for (i, chunk) in chunks.iter().enumerate().take(5) {
-        let preview = if chunk.len() > 50 {
-            format!("{}...", &chunk[..50])
+        let preview = if chunk.chars().count() > 50 {
+            format!("{}...", chunk.chars().take(50).collect::<String>())
         } else {
             chunk.clone()
         };
@@ -117,35 +239,390 @@ async fn embed_text(
     }
 
     // Generate embeddings
-    info!("Generating embeddings using {}...", model_name);
-    let vectors = openai_client.embed(&chunks).await?;
+    info!("Generating embeddings using {}...", embedder.model_name());
+    let vectors = embedder.embed(&chunks).await?;
 
-    // Prepare payloads with metadata
-    let ids: Vec<u64> = (1..=chunks.len() as u64).collect();
+    // Derive content-addressed ids so different sources never overwrite each other;
+    // records with their own id keep the same point across runs
+    let ids: Vec<PointId> = chunks_data
+        .iter()
+        .zip(&chunks)
+        .enumerate()
+        .map(|(i, (item, chunk))| {
+            let source = item.get("source").and_then(|s| s.as_str()).unwrap_or_default();
+            match item.get("record_id") {
+                Some(record_id) => PointId::for_record(source, record_id),
+                None => PointId::for_chunk(source, i, chunk),
+            }
+        })
+        .collect();
+
+    // Prefer the size of the vectors actually returned, since self-hosted models
+    // are not always in the known dimension table
+    let vector_size = vectors
+        .first()
+        .map(|vector| vector.len())
+        .unwrap_or_else(|| embedder.dimension());
 
     // Initialize Qdrant and store vectors
-    let qdrant = QdrantService::new(
-        Some(collection_name.to_string()),
-        Some(openai_client.get_embedding_dimension()),
-    )
-    .await?;
+    let qdrant = QdrantService::new(Some(collection_name.to_string()), Some(vector_size)).await?;
+
+    // Keyword weights for hybrid search are computed locally from the chunk text
+    let encoder = Bm25Encoder::default();
+    let sparse_vectors = chunks.iter().map(|chunk| encoder.encode_document(chunk)).collect();
 
-    qdrant.upsert(ids, vectors, chunks_data).await?;
+    qdrant.upsert(ids.clone(), vectors, sparse_vectors, chunks_data).await?;
 
     info!(
         "Successfully embedded {} chunks into collection '{}'",
         chunks.len(),
         collection_name
     );
-    Ok(true)
+    Ok(ids)
 }
 
-/// Main entry point for the embed command.
-pub async fn run_embed(
-    file: Option<String>,
-    text: Option<String>,
-    list_files: bool,
+/// Embed text chunks and store in vector database.
+///
+/// Returns the number of chunks stored, which is zero when the text produced no chunks.
+async fn embed_text(
+    text: &str,
+    source_name: &str,
+    embedder: &dyn EmbeddingProvider,
+    collection_name: &str,
+    chunker: &dyn Chunker,
+) -> Result<usize> {
+    // The hash tells apart texts embedded under the same source name
+    let hash = content_hash(text);
+    let mut chunks_data = chunk_text(text, chunker, source_name);
+    for item in chunks_data.iter_mut() {
+        item.insert("source_hash".to_string(), Value::String(hash.clone()));
+    }
+    Ok(embed_chunks(chunks_data, embedder, collection_name).await?.len())
+}
+
+/// Result of embedding a single file.
+enum FileOutcome {
+    Unchanged,
+    Empty,
+    Embedded(usize),
+}
+
+/// How files are turned into chunks.
+struct FileOptions<'a> {
+    chunker: &'a dyn Chunker,
+    /// Chunker for Markdown files, unless `chunker` applies to them too.
+    markdown_chunker: Option<&'a dyn Chunker>,
+    template: &'a RecordTemplate,
+    /// Re-embed files even if they are unchanged.
+    force: bool,
+}
+
+/// Embed a file unless it is unchanged since it was last embedded.
+///
+/// When a changed file is re-embedded, the points of its previous version are
+/// removed once the new ones are stored.
+async fn embed_file(
+    path: &str,
+    embedder: &dyn EmbeddingProvider,
+    collection_name: &str,
+    options: &FileOptions<'_>,
+    index: Option<&QdrantService>,
+    previous: Option<&SourceState>,
+) -> Result<FileOutcome> {
+    // Unchanged modification time is a cheap signal that the content is the same
+    if let (false, Some(state)) = (options.force, previous) {
+        if state.content_hash.is_some() && state.mtime.is_some() && state.mtime == file_mtime(path) {
+            return Ok(FileOutcome::Unchanged);
+        }
+    }
+
+    let chunks_data = process_file(path, options.chunker, options.markdown_chunker, options.template)?;
+    let new_hash = chunks_data
+        .first()
+        .and_then(|item| item.get("source_hash"))
+        .and_then(|hash| hash.as_str())
+        .map(|hash| hash.to_string());
+
+    if let (false, Some(state)) = (options.force, previous) {
+        if new_hash.is_some() && state.content_hash == new_hash {
+            // Touched but not edited: remember the new time so the next run
+            // can skip the file without reading it
+            if let (Some(index), Some(mtime)) = (index, file_mtime(path)) {
+                if let Err(e) = index.set_source_mtime(path, mtime).await {
+                    warn!("Could not update the modification time of {}: {}", path, e);
+                }
+            }
+            return Ok(FileOutcome::Unchanged);
+        }
+    }
+
+    let stored = embed_chunks(chunks_data, embedder, collection_name).await?;
+
+    // Drop the points of the previous version, or all of them if the file is now empty.
+    // A forced run may chunk the same content differently, so the old points cannot
+    // be told apart by hash and everything but the points just stored goes.
+    if let (Some(index), Some(_)) = (index, previous) {
+        if options.force {
+            index.delete_source_except(path, stored.clone()).await?;
+        } else {
+            index.delete_source(path, new_hash.as_deref()).await?;
+        }
+    }
+
+    if stored.is_empty() {
+        return Ok(FileOutcome::Empty);
+    }
+    Ok(FileOutcome::Embedded(stored.len()))
+}
+
+/// Embed every matching text file under a directory, one source per file.
+///
+/// Files are indexed incrementally: unchanged files are skipped, changed files are
+/// re-embedded and sources under the directory that no longer exist are removed.
+async fn embed_directory(
+    directory: &str,
+    include: &[String],
+    exclude: &[String],
+    embedder: &dyn EmbeddingProvider,
+    collection_name: &str,
+    options: &FileOptions<'_>,
 ) -> Result<()> {
+    let files = walk_text_files(directory, include, exclude)?;
+
+    let index = QdrantService::open_existing(collection_name).await?;
+    let existing = match &index {
+        Some(index) => index.list_sources().await?,
+        None => HashMap::new(),
+    };
+
+    if files.is_empty() && existing.is_empty() {
+        return Err(anyhow!("No matching text files found under {}", directory));
+    }
+
+    let mut embedded_files = 0;
+    let mut unchanged_files = 0;
+    let mut empty_files = 0;
+    let mut failed_files = Vec::new();
+    let mut total_chunks = 0;
+
+    for (i, path) in files.iter().enumerate() {
+        info!("[{}/{}] Embedding {}", i + 1, files.len(), path);
+
+        let outcome = embed_file(
+            path,
+            embedder,
+            collection_name,
+            options,
+            index.as_ref(),
+            existing.get(path),
+        )
+        .await;
+
+        match outcome {
+            Ok(FileOutcome::Unchanged) => {
+                info!("Unchanged, skipping {}", path);
+                unchanged_files += 1;
+            }
+            Ok(FileOutcome::Empty) => empty_files += 1,
+            Ok(FileOutcome::Embedded(chunks)) => {
+                embedded_files += 1;
+                total_chunks += chunks;
+            }
+            Err(e) => {
+                error!("Error embedding {}: {}", path, e);
+                failed_files.push(path.clone());
+            }
+        }
+    }
+
+    // Remove sources from this directory whose files have disappeared
+    let mut removed_files = 0;
+    if let Some(index) = &index {
+        for source in deleted_sources(directory, &existing) {
+            info!("Removing points of deleted file {}", source);
+            match index.delete_source(source, None).await {
+                Ok(()) => removed_files += 1,
+                Err(e) => error!("Error removing points of {}: {}", source, e),
+            }
+        }
+    }
+
+    info!(
+        "Embedded {} chunks from {} of {} files under {} ({} unchanged, {} empty, {} failed, {} removed)",
+        total_chunks,
+        embedded_files,
+        files.len(),
+        directory,
+        unchanged_files,
+        empty_files,
+        failed_files.len(),
+        removed_files
+    );
+    for path in &failed_files {
+        error!("Failed: {}", path);
+    }
+
+    if !failed_files.is_empty() && embedded_files == 0 && unchanged_files == 0 {
+        return Err(anyhow!("No files were embedded"));
+    }
+    Ok(())
+}
+
+/// Sources embedded from files under `directory` whose files no longer exist.
+///
+/// Only sources stored by the file walker, which records the file's modification
+/// time and content hash, are considered; text, stdin and `--source-name` uploads
+/// are not files and are never pruned.
+fn deleted_sources<'a>(directory: &str, existing: &'a HashMap<String, SourceState>) -> Vec<&'a str> {
+    // Sources may have been embedded with another spelling of the same
+    // directory, e.g. `docs` and `./docs`
+    let root = canonical_path(Path::new(directory));
+    let mut deleted: Vec<&str> = existing
+        .iter()
+        .filter(|(_, state)| state.mtime.is_some() && state.content_hash.is_some())
+        .map(|(source, _)| source.as_str())
+        .filter(|source| {
+            let source_path = Path::new(source);
+            !source_path.exists() && canonical_path(source_path).starts_with(&root)
+        })
+        .collect();
+    deleted.sort_unstable();
+    deleted
+}
+
+/// Resolve a path to its canonical form, even when it no longer exists.
+///
+/// The deepest existing ancestor is canonicalised and the missing components
+/// are appended to it.
+fn canonical_path(path: &Path) -> PathBuf {
+    if let Ok(canonical) = path.canonicalize() {
+        return canonical;
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => canonical_path(parent).join(name),
+        // Relative paths end in an empty parent, which is the working directory
+        _ if path.as_os_str().is_empty() => env::current_dir().unwrap_or_default(),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Embed one file into the configured collection, skipping it when unchanged.
+async fn embed_single_file(
+    path: &str,
+    embedder: &dyn EmbeddingProvider,
+    options: &FileOptions<'_>,
+) -> Result<()> {
+    let index = QdrantService::open_existing(&QDRANT_COLLECTION).await?;
+    let previous = match &index {
+        Some(index) => index.source_state(path).await?,
+        None => None,
+    };
+
+    match embed_file(
+        path,
+        embedder,
+        &QDRANT_COLLECTION,
+        options,
+        index.as_ref(),
+        previous.as_ref(),
+    )
+    .await
+    {
+        Ok(FileOutcome::Unchanged) => {
+            info!("{} is unchanged since it was last embedded (use --force to re-embed)", path);
+            Ok(())
+        }
+        Ok(FileOutcome::Empty) => Err(anyhow!("Failed to embed text")),
+        Ok(FileOutcome::Embedded(_)) => {
+            info!("Text successfully embedded");
+            Ok(())
+        }
+        Err(e) => Err(anyhow!("Error embedding text: {}", e)),
+    }
+}
+
+/// A record of JSON Lines input read from stdin.
+#[derive(Debug, Deserialize)]
+struct StdinRecord {
+    id: Option<Value>,
+    text: String,
+    #[serde(default)]
+    metadata: Map<String, Value>,
+}
+
+/// Parse JSON Lines input into one document part per record.
+///
+/// The record metadata is copied into the payload, with reserved keys renamed by
+/// `record_field_key`, and the id is kept as `record_id`.
+fn parse_jsonl_records(input: &str) -> Result<Vec<DocumentPart>> {
+    let mut parts = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: StdinRecord = serde_json::from_str(line)
+            .map_err(|e| anyhow!("Invalid record on line {}: {}", i + 1, e))?;
+
+        let mut part = DocumentPart::new(record.text);
+        part.metadata.extend(
+            record.metadata
+                .into_iter()
+                .map(|(field, value)| (record_field_key(&field), value)),
+        );
+        if let Some(id) = record.id.filter(|id| !id.is_null()) {
+            part.metadata.insert("record_id".to_string(), id);
+        }
+        parts.push(part);
+    }
+    Ok(parts)
+}
+
+/// Embed input piped on stdin under the given source name.
+///
+/// Non-empty plain text replaces the previous version of the source. JSON Lines records are
+/// embedded one point per record; records with an `id` update their previous point.
+async fn embed_stdin(
+    source_name: &str,
+    format: InputFormat,
+    embedder: &dyn EmbeddingProvider,
+    chunker: &dyn Chunker,
+) -> Result<()> {
+    let mut input = String::new();
+    io::stdin().lock().read_to_string(&mut input)?;
+
+    let chunks = match format {
+        InputFormat::Text => {
+            let hash = content_hash(&input);
+            let mut chunks_data = chunk_text(&input, chunker, source_name);
+            for item in chunks_data.iter_mut() {
+                item.insert("source_hash".to_string(), Value::String(hash.clone()));
+            }
+
+            // Empty input leaves the previous version in place rather than wiping it
+            let chunks = embed_chunks(chunks_data, embedder, &QDRANT_COLLECTION).await?.len();
+            if chunks > 0 {
+                if let Some(index) = QdrantService::open_existing(&QDRANT_COLLECTION).await? {
+                    index.delete_source(source_name, Some(&hash)).await?;
+                }
+            }
+            chunks
+        }
+        InputFormat::Jsonl => {
+            let parts = parse_jsonl_records(&input)?;
+            let chunks_data = chunk_parts(&parts, &RecordChunker, source_name);
+            embed_chunks(chunks_data, embedder, &QDRANT_COLLECTION).await?.len()
+        }
+    };
+
+    if chunks == 0 {
+        return Err(anyhow!("No input text provided"));
+    }
+    info!("Text successfully embedded");
+    Ok(())
+}
+
+/// Main entry point for the embed command.
+pub async fn run_embed(args: EmbedArgs) -> Result<()> {
     // Validate environment
     if !validate_environment() {
         error!("Environment validation failed");
@@ -153,8 +630,11 @@ pub async fn run_embed(
     }
 
     // List files if requested
-    if list_files {
-        let files = list_text_files(".")?;
+    if args.list_files {
+        let files = match &args.dir {
+            Some(dir) => walk_text_files(dir, &args.include, &args.exclude)?,
+            None => list_text_files(".")?,
+        };
         if !files.is_empty() {
             info!("Available text files:");
             for file in files {
@@ -166,30 +646,148 @@ pub async fn run_embed(
         return Ok(());
     }
 
+    // Initialize the configured embedding provider and chunker
+    let embedder = create_embedding_provider(Some(DEFAULT_EMBEDDING_MODEL.clone()))?;
+    let chunker = build_chunker(&args, embedder.model_name())?;
+    let template = RecordTemplate::new(args.text_fields.clone(), args.template.clone());
+    // Markdown files are split by section unless --chunker picks a strategy for all files
+    let markdown_chunker = MarkdownChunker::new(args.max_chars);
+    let options = FileOptions {
+        chunker: chunker.as_ref(),
+        markdown_chunker: match args.chunker {
+            Some(_) => None,
+            None => Some(&markdown_chunker),
+        },
+        template: &template,
+        force: args.force,
+    };
+
+    // Embed a whole directory tree if requested
+    if let Some(dir) = &args.dir {
+        return embed_directory(
+            dir,
+            &args.include,
+            &args.exclude,
+            embedder.as_ref(),
+            &QDRANT_COLLECTION,
+            &options,
+        )
+        .await;
+    }
+
+    // Embed a single file incrementally if requested
+    if let Some(path) = &args.file {
+        return embed_single_file(path, embedder.as_ref(), &options).await;
+    }
+
+    // Embed piped input if requested
+    if args.stdin {
+        return embed_stdin(&args.source_name, args.format, embedder.as_ref(), chunker.as_ref()).await;
+    }
+
     // Get input text
-    let input_data = get_input_text(file, text, list_files).await?;
+    let input_data = get_input_text(args.text, args.list_files).await?;
     if input_data.is_none() {
         error!("No input text provided");
         return Err(anyhow!("No input text provided"));
     }
 
-    let (text, source) = input_data.unwrap();
-    
+    let (text, source) = match input_data.unwrap() {
+        Input::File(path) => {
+            return embed_single_file(&path, embedder.as_ref(), &options).await;
+        }
+        Input::Text { text, source } => (text, source),
+    };
+
     // Embed text
     match embed_text(
         &text,
         &source,
-        &DEFAULT_EMBEDDING_MODEL,
+        embedder.as_ref(),
         &QDRANT_COLLECTION,
-        DEFAULT_MAX_SENTENCES_PER_CHUNK,
+        chunker.as_ref(),
     )
     .await
     {
-        Ok(true) => {
+        Ok(0) => Err(anyhow!("Failed to embed text")),
+        Ok(_) => {
             info!("Text successfully embedded");
             Ok(())
         }
-        Ok(false) => Err(anyhow!("Failed to embed text")),
         Err(e) => Err(anyhow!("Error embedding text: {}", e)),
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Debug, Parser)]
+    struct Cli {
+        #[command(flatten)]
+        embed: EmbedArgs,
+    }
+
+    #[test]
+    fn stdin_conflicts_with_other_inputs() {
+        assert!(Cli::try_parse_from(["embed", "--stdin"]).is_ok());
+        for other in [["--file", "a.txt"], ["--dir", "docs"], ["--text", "hello"]] {
+            let args = ["embed", "--stdin", other[0], other[1]];
+            assert!(Cli::try_parse_from(args).is_err(), "{:?} was accepted", args);
+        }
+    }
+
+    #[test]
+    fn format_requires_stdin() {
+        assert!(Cli::try_parse_from(["embed", "--stdin", "--format", "jsonl"]).is_ok());
+        assert!(Cli::try_parse_from(["embed", "--file", "a.txt"]).is_ok());
+        for args in [
+            &["embed", "--format", "jsonl"][..],
+            &["embed", "--file", "a.jsonl", "--format", "jsonl"],
+            &["embed", "--dir", "docs", "--format", "text"],
+        ] {
+            assert!(Cli::try_parse_from(args).is_err(), "{:?} was accepted", args);
+        }
+    }
+
+    #[test]
+    fn canonical_path_ignores_spelling_and_handles_missing_files() {
+        let dot = canonical_path(Path::new("./src"));
+        assert_eq!(canonical_path(Path::new("src")), dot);
+        assert!(dot.is_absolute());
+
+        let missing = canonical_path(Path::new("./src/../src/no-such-dir/deleted.md"));
+        assert_eq!(missing, dot.join("no-such-dir").join("deleted.md"));
+        assert!(missing.starts_with(&dot));
+    }
+
+    fn file_state() -> SourceState {
+        SourceState {
+            content_hash: Some("hash".to_string()),
+            mtime: Some(1_700_000_000),
+            points: 1,
+        }
+    }
+
+    #[test]
+    fn deleted_sources_only_prunes_missing_files_under_the_directory() {
+        let text_state = SourceState {
+            content_hash: Some("hash".to_string()),
+            mtime: None,
+            points: 1,
+        };
+        let existing = HashMap::from([
+            ("command_line_input".to_string(), text_state.clone()),
+            ("stdin".to_string(), text_state),
+            ("team-notes".to_string(), SourceState::default()),
+            ("src/main.rs".to_string(), file_state()),
+            ("./src/deleted.md".to_string(), file_state()),
+            ("../elsewhere/deleted.md".to_string(), file_state()),
+        ]);
+
+        assert_eq!(deleted_sources(".", &existing), ["./src/deleted.md"]);
+        assert_eq!(deleted_sources("src", &existing), ["./src/deleted.md"]);
+        assert!(deleted_sources("target", &existing).is_empty());
+    }
+}
@@ -1,2 +1,3 @@
 pub mod chat;
+pub mod commands;
 pub mod embed; 
\ No newline at end of file
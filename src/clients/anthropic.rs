@@ -0,0 +1,206 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use reqwest::Client as HttpClient;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::clients::chat::{line_deltas, ChatMessage, ChatProvider, ChatStream, StreamLine};
+use crate::config::{ANTHROPIC_API_KEY, ANTHROPIC_BASE_URL, ANTHROPIC_MAX_TOKENS, DEFAULT_CHAT_MODEL};
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+// Anthropic Messages API types
+#[derive(Debug, Serialize)]
+struct MessagesRequest {
+    model: String,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    messages: Vec<ChatMessage>,
+    temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContentBlock {
+    #[serde(rename = "type")]
+    block_type: String,
+    text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessagesResponse {
+    content: Vec<ContentBlock>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamDelta {
+    text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamEvent {
+    #[serde(rename = "type")]
+    event_type: String,
+    delta: Option<StreamDelta>,
+    error: Option<Value>,
+}
+
+/// Client for the Anthropic Messages API.
+pub struct AnthropicClient {
+    client: HttpClient,
+    base_url: String,
+    api_key: String,
+    chat_model: String,
+    max_tokens: u32,
+}
+
+impl AnthropicClient {
+    /// Initialize Anthropic client for chat completions.
+    pub fn new(api_key: Option<String>, chat_model: Option<String>) -> Result<Self> {
+        let api_key = api_key.or_else(|| ANTHROPIC_API_KEY.clone()).ok_or_else(|| {
+            anyhow!("Anthropic API key is required. Set ANTHROPIC_API_KEY environment variable or pass as parameter.")
+        })?;
+
+        Ok(Self {
+            client: HttpClient::new(),
+            base_url: ANTHROPIC_BASE_URL.trim_end_matches('/').to_string(),
+            api_key,
+            chat_model: chat_model.unwrap_or_else(|| DEFAULT_CHAT_MODEL.clone()),
+            max_tokens: *ANTHROPIC_MAX_TOKENS,
+        })
+    }
+
+    /// Build a request, moving system messages into the top-level `system` field
+    /// since the Messages API only accepts user and assistant turns.
+    fn build_request(&self, messages: &[ChatMessage], temperature: f32, stream: bool) -> MessagesRequest {
+        let system: Vec<&str> = messages
+            .iter()
+            .filter(|msg| msg.role == "system")
+            .map(|msg| msg.content.as_str())
+            .collect();
+
+        MessagesRequest {
+            model: self.chat_model.clone(),
+            max_tokens: self.max_tokens,
+            system: if system.is_empty() { None } else { Some(system.join("\n\n")) },
+            messages: messages
+                .iter()
+                .filter(|msg| msg.role != "system")
+                .cloned()
+                .collect(),
+            // Anthropic accepts temperatures between 0 and 1
+            temperature: temperature.clamp(0.0, 1.0),
+            stream: if stream { Some(true) } else { None },
+        }
+    }
+
+    /// Send a messages request and return the raw response.
+    async fn send_request(&self, request: &MessagesRequest) -> Result<reqwest::Response> {
+        let response = self.client
+            .post(format!("{}/v1/messages", self.base_url))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .header("Content-Type", "application/json")
+            .json(request)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            return Err(anyhow!("API error: {}", error_text));
+        }
+
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl ChatProvider for AnthropicClient {
+    async fn complete(&self, messages: &[ChatMessage], temperature: f32) -> Result<String> {
+        let request = self.build_request(messages, temperature, false);
+        let response = self.send_request(&request).await?;
+
+        let messages_response: MessagesResponse = response.json().await?;
+        let content: String = messages_response
+            .content
+            .into_iter()
+            .filter(|block| block.block_type == "text")
+            .filter_map(|block| block.text)
+            .collect();
+
+        if content.is_empty() {
+            return Err(anyhow!("No content in response"));
+        }
+        Ok(content)
+    }
+
+    async fn stream(&self, messages: &[ChatMessage], temperature: f32) -> Result<ChatStream> {
+        let request = self.build_request(messages, temperature, true);
+        let response = self.send_request(&request).await?;
+
+        Ok(line_deltas(response.bytes_stream(), parse_sse_line))
+    }
+
+    fn model_name(&self) -> &str {
+        &self.chat_model
+    }
+}
+
+/// Parse one server-sent event line of a streamed Messages API response.
+fn parse_sse_line(line: &str) -> Result<StreamLine> {
+    let data = match line.strip_prefix("data:") {
+        Some(data) => data.trim(),
+        // The event type is repeated inside the data payload, so `event:` lines can be ignored
+        None => return Ok(StreamLine::Skip),
+    };
+
+    let event: StreamEvent = serde_json::from_str(data)
+        .map_err(|e| anyhow!("Failed to parse stream event: {} - Event was: {}", e, data))?;
+
+    match event.event_type.as_str() {
+        "content_block_delta" => match event.delta.and_then(|delta| delta.text) {
+            Some(text) if !text.is_empty() => Ok(StreamLine::Delta(text)),
+            _ => Ok(StreamLine::Skip),
+        },
+        "message_stop" => Ok(StreamLine::Done),
+        "error" => Err(anyhow!(
+            "API error: {}",
+            event.error.unwrap_or(Value::Null)
+        )),
+        _ => Ok(StreamLine::Skip),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::chat::collect_deltas;
+
+    #[test]
+    fn sse_events_yield_text_deltas_and_stop() {
+        let delta = r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hi"}}"#;
+        assert_eq!(parse_sse_line(delta).unwrap(), StreamLine::Delta("Hi".to_string()));
+        assert_eq!(parse_sse_line(r#"data: {"type":"message_stop"}"#).unwrap(), StreamLine::Done);
+        assert_eq!(parse_sse_line("event: content_block_delta").unwrap(), StreamLine::Skip);
+        assert_eq!(parse_sse_line(r#"data: {"type":"ping"}"#).unwrap(), StreamLine::Skip);
+
+        let error = r#"data: {"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        assert!(parse_sse_line(error).unwrap_err().to_string().contains("Overloaded"));
+    }
+
+    #[tokio::test]
+    async fn sse_stream_reads_events_until_message_stop() {
+        let deltas = collect_deltas(
+            &[
+                b"event: message_start\ndata: {\"type\":\"message_start\"}\n\n",
+                b"event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"delta\":{\"text\":\"Hello\"}}\n\n",
+                b"event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+            ],
+            parse_sse_line,
+        )
+        .await;
+        assert_eq!(deltas, [Ok("Hello".to_string())]);
+    }
+}
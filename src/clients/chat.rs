@@ -0,0 +1,864 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures::stream::{self, Stream, StreamExt};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use crate::clients::anthropic::AnthropicClient;
+use crate::clients::ollama::OllamaClient;
+use crate::clients::openai::OpenAIClient;
+use crate::clients::tokens::{context_limit, TokenCounter};
+use crate::config::{
+    CHAT_PROVIDER, KEEP_CONTEXTS, MOCK_CHAT_SCRIPT, QUERY_REWRITE_HISTORY, RESPONSE_TOKEN_RESERVE,
+    SUMMARY_TOKEN_BUDGET,
+};
+
+/// A single message in a chat conversation.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+impl ChatMessage {
+    /// Create a message with the given role.
+    pub fn new(role: &str, content: &str) -> Self {
+        Self {
+            role: role.to_string(),
+            content: content.to_string(),
+        }
+    }
+}
+
+/// Saved state of a conversation, as written by the chat's `/save` command.
+///
+/// Retrieved context is not saved, as it only ever belongs to a single turn.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Transcript {
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    pub messages: Vec<ChatMessage>,
+}
+
+/// Stream of content deltas from a streamed chat completion.
+pub type ChatStream = Pin<Box<dyn Stream<Item = Result<String>> + Send>>;
+
+/// A backend that produces chat completions for a list of messages.
+#[async_trait]
+pub trait ChatProvider: Send + Sync {
+    /// Get the full completion for the given messages.
+    async fn complete(&self, messages: &[ChatMessage], temperature: f32) -> Result<String>;
+
+    /// Stream the completion for the given messages as content deltas.
+    ///
+    /// Providers without native streaming yield the full completion as a single delta.
+    async fn stream(&self, messages: &[ChatMessage], temperature: f32) -> Result<ChatStream> {
+        let content = self.complete(messages, temperature).await?;
+        Ok(Box::pin(stream::once(async move { Ok(content) })))
+    }
+
+    /// Name of the model answering the conversation.
+    fn model_name(&self) -> &str;
+
+    /// Size of the context window requests are fitted into, in tokens.
+    fn context_limit(&self) -> usize {
+        context_limit(self.model_name())
+    }
+}
+
+/// What happens to the oldest turns once the conversation outgrows the model's context window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryStrategy {
+    /// Drop them.
+    Truncate,
+    /// Condense them into a running summary sent in their place.
+    Summarize,
+}
+
+impl FromStr for HistoryStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name.to_lowercase().as_str() {
+            "truncate" | "drop" => Ok(Self::Truncate),
+            "summarize" | "summarise" | "summary" => Ok(Self::Summarize),
+            other => Err(anyhow!(
+                "Unknown history strategy '{}'. Expected one of: truncate, summarize",
+                other
+            )),
+        }
+    }
+}
+
+/// Conversation state on top of a chat provider.
+///
+/// Retrieved context is not part of the history: it is attached to the user
+/// message it was retrieved for and only sent along while that message is one
+/// of the last `max_contexts` turns with context.
+///
+/// Before each request the conversation is fitted into the model's context
+/// window according to the history strategy.
+pub struct ChatSession {
+    provider: Box<dyn ChatProvider>,
+    conversation_history: Vec<ChatMessage>,
+    /// Retrieved context blocks, with the history index of their user message
+    /// and the highest number of the contexts they list.
+    contexts: VecDeque<(usize, String, usize)>,
+    max_contexts: usize,
+    /// Running summary of turns dropped from the history.
+    summary: Option<String>,
+    history_strategy: HistoryStrategy,
+    token_counter: Option<TokenCounter>,
+    context_limit: usize,
+}
+
+impl ChatSession {
+    /// Start an empty conversation with the given provider.
+    pub fn new(provider: Box<dyn ChatProvider>) -> Self {
+        let token_counter = TokenCounter::new(provider.model_name()).ok();
+        let context_limit = provider.context_limit();
+        Self {
+            provider,
+            conversation_history: Vec::new(),
+            contexts: VecDeque::new(),
+            max_contexts: *KEEP_CONTEXTS,
+            summary: None,
+            history_strategy: HistoryStrategy::Truncate,
+            token_counter,
+            context_limit,
+        }
+    }
+
+    /// Continue the conversation with another provider or model.
+    pub fn set_provider(&mut self, provider: Box<dyn ChatProvider>) {
+        self.token_counter = TokenCounter::new(provider.model_name()).ok();
+        self.context_limit = provider.context_limit();
+        self.provider = provider;
+    }
+
+    /// Snapshot of the conversation for saving.
+    pub fn transcript(&self) -> Transcript {
+        Transcript {
+            model: self.provider.model_name().to_string(),
+            summary: self.summary.clone(),
+            messages: self.conversation_history.clone(),
+        }
+    }
+
+    /// Replace the conversation with a saved one.
+    pub fn restore(&mut self, transcript: Transcript) {
+        self.conversation_history = transcript.messages;
+        self.summary = transcript.summary;
+        self.contexts.clear();
+    }
+
+    /// Set how turns that no longer fit the context window are handled.
+    pub fn set_history_strategy(&mut self, strategy: HistoryStrategy) {
+        self.history_strategy = strategy;
+    }
+
+    /// Set how many of the most recent context blocks are sent with each request (at least 1).
+    pub fn set_max_contexts(&mut self, max_contexts: usize) {
+        self.max_contexts = max_contexts.max(1);
+        while self.contexts.len() > self.max_contexts {
+            self.contexts.pop_front();
+        }
+    }
+
+    /// Add a system message to the conversation history.
+    pub fn add_system_message(&mut self, content: &str) {
+        self.conversation_history.push(ChatMessage::new("system", content));
+    }
+
+    /// Add a user message to the conversation history.
+    pub fn add_user_message(&mut self, content: &str) {
+        self.conversation_history.push(ChatMessage::new("user", content));
+    }
+
+    /// Add an assistant message to the conversation history.
+    pub fn add_assistant_message(&mut self, content: &str) {
+        self.conversation_history.push(ChatMessage::new("assistant", content));
+    }
+
+    /// Number to give the first context of a block attached to the latest user
+    /// message, continuing after the blocks sent along with it so that citations
+    /// such as `[2]` are unambiguous.
+    pub fn next_context_number(&self) -> usize {
+        let latest = self.conversation_history.iter().rposition(|msg| msg.role == "user");
+        self.contexts
+            .iter()
+            .rev()
+            .filter(|(index, _, _)| Some(*index) != latest)
+            .take(self.max_contexts.saturating_sub(1))
+            .map(|(_, _, last_number)| *last_number)
+            .max()
+            .unwrap_or(0)
+            + 1
+    }
+
+    /// Attach retrieved context to the latest user message.
+    ///
+    /// `last_number` is the highest context number the block lists. The context
+    /// is sent as a system message after that user message, and dropped once
+    /// more than `max_contexts` newer turns have context.
+    pub fn attach_context(&mut self, content: &str, last_number: usize) {
+        let index = match self.conversation_history.iter().rposition(|msg| msg.role == "user") {
+            Some(index) => index,
+            None => return,
+        };
+        self.contexts.retain(|(i, _, _)| *i != index);
+        self.contexts.push_back((index, content.to_string(), last_number));
+        while self.contexts.len() > self.max_contexts {
+            self.contexts.pop_front();
+        }
+    }
+
+    /// Messages sent to the model: the history with the running summary after
+    /// the leading system messages and the kept context blocks inserted after
+    /// their user messages.
+    fn request_messages(&self) -> Vec<ChatMessage> {
+        let mut messages = Vec::with_capacity(self.conversation_history.len() + self.contexts.len() + 1);
+        let mut summary = self.summary.as_ref();
+        for (i, msg) in self.conversation_history.iter().enumerate() {
+            if msg.role != "system" {
+                if let Some(summary) = summary.take() {
+                    messages.push(ChatMessage::new(
+                        "system",
+                        &format!("Summary of the earlier conversation:\n{}", summary),
+                    ));
+                }
+            }
+            messages.push(msg.clone());
+            if let Some((_, context, _)) = self.contexts.iter().find(|(index, _, _)| *index == i) {
+                messages.push(ChatMessage::new("system", context));
+            }
+        }
+        messages
+    }
+
+    /// Number of tokens the next request takes up, including attached context.
+    pub fn token_count(&self) -> Option<usize> {
+        let counter = self.token_counter.as_ref()?;
+        Some(counter.count_messages(&self.request_messages()))
+    }
+
+    /// Drop (and with `HistoryStrategy::Summarize`, summarise) the oldest turns
+    /// until the next request fits the context window with room for the reply.
+    ///
+    /// The latest user turn is always kept, and turns are dropped whole so the
+    /// history still starts with a user message.
+    async fn fit_history(&mut self) {
+        let counter = match &self.token_counter {
+            Some(counter) => counter,
+            None => return,
+        };
+        let budget = self.context_limit.saturating_sub(RESPONSE_TOKEN_RESERVE);
+        let tokens = counter.count_messages(&self.request_messages());
+        debug!("Conversation uses {} of {} tokens", tokens, budget);
+        if tokens <= budget {
+            return;
+        }
+
+        // Leave room for the summary replacing the dropped turns
+        let target = match self.history_strategy {
+            HistoryStrategy::Truncate => budget,
+            HistoryStrategy::Summarize => budget.saturating_sub(SUMMARY_TOKEN_BUDGET.min(budget / 4)),
+        };
+        let latest = self
+            .conversation_history
+            .iter()
+            .rposition(|msg| msg.role == "user")
+            .unwrap_or(self.conversation_history.len());
+
+        let mut remaining = tokens;
+        let mut dropped = Vec::new();
+        for (i, msg) in self.conversation_history.iter().enumerate().take(latest) {
+            if msg.role == "system" {
+                continue;
+            }
+            if remaining <= target && msg.role == "user" {
+                break;
+            }
+            remaining = remaining.saturating_sub(counter.count_messages(std::slice::from_ref(msg)));
+            if let Some((_, context, _)) = self.contexts.iter().find(|(index, _, _)| *index == i) {
+                remaining = remaining.saturating_sub(counter.count_text(context));
+            }
+            dropped.push(i);
+        }
+
+        if dropped.is_empty() {
+            warn!(
+                "The latest turn alone takes {} tokens, more than the {} available for {}",
+                tokens,
+                budget,
+                self.provider.model_name()
+            );
+            return;
+        }
+
+        if self.history_strategy == HistoryStrategy::Summarize {
+            let turns: Vec<&ChatMessage> = dropped.iter().map(|&i| &self.conversation_history[i]).collect();
+            match self.summarize(&turns).await {
+                Ok(summary) => self.summary = Some(summary),
+                Err(e) => warn!("Could not summarise old turns, dropping them: {}", e),
+            }
+        }
+
+        // Remove the dropped messages and shift the context indices accordingly
+        let history = std::mem::take(&mut self.conversation_history);
+        let mut new_indices = Vec::with_capacity(history.len());
+        for (i, msg) in history.into_iter().enumerate() {
+            if dropped.binary_search(&i).is_ok() {
+                new_indices.push(None);
+            } else {
+                new_indices.push(Some(self.conversation_history.len()));
+                self.conversation_history.push(msg);
+            }
+        }
+        self.contexts = self
+            .contexts
+            .drain(..)
+            .filter_map(|(index, context, last_number)| {
+                new_indices[index].map(|index| (index, context, last_number))
+            })
+            .collect();
+
+        info!(
+            "Dropped {} old messages to fit the {}-token context window of {}",
+            dropped.len(),
+            self.context_limit,
+            self.provider.model_name()
+        );
+    }
+
+    /// Fold turns into the running summary.
+    async fn summarize(&self, turns: &[&ChatMessage]) -> Result<String> {
+        let transcript = turns
+            .iter()
+            .map(|msg| {
+                let speaker = if msg.role == "user" { "User" } else { "Assistant" };
+                format!("{}: {}", speaker, msg.content)
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let previous = match &self.summary {
+            Some(summary) => format!("Summary so far:\n{}\n\n", summary),
+            None => String::new(),
+        };
+
+        let messages = [
+            ChatMessage::new(
+                "system",
+                "Summarise the conversation for an assistant that will continue it without seeing it. \
+                Keep facts, names, numbers, decisions and open questions; drop pleasantries. \
+                Reply with only the summary, in at most 200 words.",
+            ),
+            ChatMessage::new("user", &format!("{}Conversation:\n{}", previous, transcript)),
+        ];
+        let summary = self.provider.complete(&messages, 0.0).await?;
+
+        let summary = summary.trim();
+        if summary.is_empty() {
+            return Err(anyhow!("Empty summary"));
+        }
+        Ok(summary.to_string())
+    }
+
+    /// Get a response from the chat model based on conversation history.
+    pub async fn get_response(&mut self, temperature: f32) -> Result<String> {
+        self.fit_history().await;
+        let content = self
+            .provider
+            .complete(&self.request_messages(), temperature)
+            .await?;
+        self.add_assistant_message(&content);
+        Ok(content)
+    }
+
+    /// Stream a response from the chat model based on conversation history.
+    ///
+    /// Unlike `get_response`, the assistant message is not added to the history;
+    /// callers should add the assembled reply with `add_assistant_message` once the
+    /// stream is exhausted.
+    pub async fn stream_response(&mut self, temperature: f32) -> Result<ChatStream> {
+        self.fit_history().await;
+        self.provider
+            .stream(&self.request_messages(), temperature)
+            .await
+    }
+
+    /// Rewrite the latest user message into a standalone search query.
+    ///
+    /// Follow-ups such as "what about the second one?" are resolved against the
+    /// preceding user and assistant turns. Without earlier turns the message is
+    /// returned unchanged and the model is not called.
+    pub async fn condense_query(&self) -> Result<String> {
+        let turns: Vec<&ChatMessage> = self
+            .conversation_history
+            .iter()
+            .filter(|msg| msg.role == "user" || msg.role == "assistant")
+            .collect();
+        let (latest, earlier) = match turns.split_last() {
+            Some((latest, earlier)) if latest.role == "user" => (latest, earlier),
+            _ => return Err(anyhow!("No user message to condense")),
+        };
+        if earlier.is_empty() {
+            return Ok(latest.content.clone());
+        }
+
+        let transcript = earlier[earlier.len().saturating_sub(QUERY_REWRITE_HISTORY)..]
+            .iter()
+            .map(|msg| {
+                let speaker = if msg.role == "user" { "User" } else { "Assistant" };
+                format!("{}: {}", speaker, msg.content)
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let messages = [
+            ChatMessage::new(
+                "system",
+                "Rewrite the user's follow-up message into a standalone search query, \
+                resolving references to the conversation so it can be understood on its own. \
+                Keep names, identifiers and numbers. Reply with only the query.",
+            ),
+            ChatMessage::new(
+                "user",
+                &format!("Conversation:\n{}\n\nFollow-up: {}", transcript, latest.content),
+            ),
+        ];
+        let rewritten = self.provider.complete(&messages, 0.0).await?;
+
+        let rewritten = rewritten.trim().trim_matches('"').trim();
+        if rewritten.is_empty() {
+            return Ok(latest.content.clone());
+        }
+        Ok(rewritten.to_string())
+    }
+
+    /// Reset the conversation history, optionally keeping system messages.
+    ///
+    /// Attached context and the running summary are always dropped, since the
+    /// messages they belong to are gone.
+    pub fn reset_conversation(&mut self, keep_system_messages: bool) {
+        self.contexts.clear();
+        self.summary = None;
+        if keep_system_messages {
+            self.conversation_history.retain(|msg| msg.role == "system");
+        } else {
+            self.conversation_history.clear();
+        }
+    }
+
+    /// Messages exchanged so far, without attached context.
+    pub fn history(&self) -> &[ChatMessage] {
+        &self.conversation_history
+    }
+
+    /// Name of the model answering the conversation.
+    pub fn model_name(&self) -> &str {
+        self.provider.model_name()
+    }
+}
+
+/// Scripted provider that replays canned responses, for tests and demos.
+///
+/// Once the script is exhausted it echoes the latest user message.
+pub struct MockChatProvider {
+    responses: Mutex<VecDeque<String>>,
+}
+
+impl MockChatProvider {
+    /// Create a provider answering with the given responses in order.
+    pub fn new(responses: Vec<String>) -> Self {
+        Self {
+            responses: Mutex::new(responses.into()),
+        }
+    }
+
+    /// Load responses from a script file, separated by lines containing only `---`.
+    pub fn from_script(path: &str) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Could not read mock chat script {}: {}", path, e))?;
+
+        let mut responses = Vec::new();
+        let mut current = Vec::new();
+        for line in content.lines() {
+            if line.trim() == "---" {
+                responses.push(current.join("\n"));
+                current.clear();
+            } else {
+                current.push(line);
+            }
+        }
+        if !current.is_empty() {
+            responses.push(current.join("\n"));
+        }
+
+        Ok(Self::new(
+            responses
+                .into_iter()
+                .map(|r| r.trim().to_string())
+                .filter(|r| !r.is_empty())
+                .collect(),
+        ))
+    }
+}
+
+#[async_trait]
+impl ChatProvider for MockChatProvider {
+    async fn complete(&self, messages: &[ChatMessage], _temperature: f32) -> Result<String> {
+        let scripted = self
+            .responses
+            .lock()
+            .map_err(|_| anyhow!("Mock chat script lock poisoned"))?
+            .pop_front();
+
+        if let Some(response) = scripted {
+            return Ok(response);
+        }
+
+        let last_user = messages
+            .iter()
+            .rev()
+            .find(|msg| msg.role == "user")
+            .map(|msg| msg.content.as_str())
+            .unwrap_or("");
+        Ok(format!("You said: {}", last_user))
+    }
+
+    fn model_name(&self) -> &str {
+        "mock"
+    }
+}
+
+/// Outcome of parsing a single line of a streamed response.
+#[derive(Debug, PartialEq)]
+pub(crate) enum StreamLine {
+    Delta(String),
+    Done,
+    Skip,
+}
+
+/// Turn a raw byte stream of newline-delimited events into a stream of content deltas.
+///
+/// Works for both server-sent events and newline-delimited JSON, depending on
+/// `parse_line`, which receives each complete, trimmed line.
+pub(crate) fn line_deltas<S, B>(bytes: S, parse_line: fn(&str) -> Result<StreamLine>) -> ChatStream
+where
+    S: Stream<Item = reqwest::Result<B>> + Send + 'static,
+    B: AsRef<[u8]>,
+{
+    let state = (
+        Box::pin(bytes),
+        Vec::<u8>::new(),
+        VecDeque::<Result<String>>::new(),
+        false,
+    );
+
+    Box::pin(stream::unfold(state, move |(mut bytes, mut buffer, mut pending, mut done)| async move {
+        loop {
+            if let Some(item) = pending.pop_front() {
+                return Some((item, (bytes, buffer, pending, done)));
+            }
+            if done {
+                return None;
+            }
+
+            match bytes.next().await {
+                Some(Ok(chunk)) => {
+                    buffer.extend_from_slice(chunk.as_ref());
+
+                    // Only complete lines are parsed so multi-byte characters split
+                    // across network chunks are decoded correctly
+                    while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+                        let line: Vec<u8> = buffer.drain(..=pos).collect();
+                        match parse_line(String::from_utf8_lossy(&line).trim()) {
+                            Ok(StreamLine::Delta(content)) => pending.push_back(Ok(content)),
+                            Ok(StreamLine::Done) => {
+                                done = true;
+                                break;
+                            }
+                            Ok(StreamLine::Skip) => {}
+                            Err(e) => {
+                                pending.push_back(Err(e));
+                                done = true;
+                                break;
+                            }
+                        }
+                    }
+                }
+                Some(Err(e)) => {
+                    pending.push_back(Err(anyhow!("Stream error: {}", e)));
+                    done = true;
+                }
+                None => {
+                    // Flush a final line that was not newline-terminated
+                    if let Ok(StreamLine::Delta(content)) =
+                        parse_line(String::from_utf8_lossy(&buffer).trim())
+                    {
+                        pending.push_back(Ok(content));
+                    }
+                    buffer.clear();
+                    done = true;
+                }
+            }
+        }
+    }))
+}
+
+/// Feed network chunks through `line_deltas` and collect the deltas, with errors as text.
+#[cfg(test)]
+pub(crate) async fn collect_deltas(
+    chunks: &[&[u8]],
+    parse_line: fn(&str) -> Result<StreamLine>,
+) -> Vec<std::result::Result<String, String>> {
+    let chunks: Vec<reqwest::Result<Vec<u8>>> = chunks.iter().map(|chunk| Ok(chunk.to_vec())).collect();
+    line_deltas(stream::iter(chunks), parse_line)
+        .map(|delta| delta.map_err(|e| e.to_string()))
+        .collect()
+        .await
+}
+
+/// Create the chat provider selected by the `CHAT_PROVIDER` setting.
+pub fn create_chat_provider(model: Option<String>) -> Result<Box<dyn ChatProvider>> {
+    match CHAT_PROVIDER.to_lowercase().as_str() {
+        "openai" => Ok(Box::new(OpenAIClient::new(None, model, None)?)),
+        "anthropic" => Ok(Box::new(AnthropicClient::new(None, model)?)),
+        "ollama" => Ok(Box::new(OllamaClient::new(None, model))),
+        "mock" => match MOCK_CHAT_SCRIPT.as_ref() {
+            Some(path) => Ok(Box::new(MockChatProvider::from_script(path)?)),
+            None => Ok(Box::new(MockChatProvider::new(Vec::new()))),
+        },
+        other => Err(anyhow!(
+            "Unknown chat provider '{}'. Expected one of: openai, anthropic, ollama, mock",
+            other
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mock_session(responses: &[&str]) -> ChatSession {
+        let responses = responses.iter().map(|r| r.to_string()).collect();
+        let mut session = ChatSession::new(Box::new(MockChatProvider::new(responses)));
+        session.add_system_message("You are a test assistant.");
+        session
+    }
+
+    #[tokio::test]
+    async fn session_replays_scripted_responses_then_echoes() {
+        let mut session = mock_session(&["first answer", "second answer"]);
+
+        session.add_user_message("one");
+        assert_eq!(session.get_response(0.0).await.unwrap(), "first answer");
+        session.add_user_message("two");
+        assert_eq!(session.get_response(0.0).await.unwrap(), "second answer");
+        session.add_user_message("three");
+        assert_eq!(session.get_response(0.0).await.unwrap(), "You said: three");
+
+        let roles: Vec<&str> = session.history().iter().map(|msg| msg.role.as_str()).collect();
+        assert_eq!(roles, ["system", "user", "assistant", "user", "assistant", "user", "assistant"]);
+        assert_eq!(session.history()[2].content, "first answer");
+        assert_eq!(session.model_name(), "mock");
+    }
+
+    #[tokio::test]
+    async fn streamed_reply_is_not_added_to_history() {
+        let mut session = mock_session(&["streamed answer"]);
+        session.add_user_message("hello");
+
+        let mut stream = session.stream_response(0.0).await.unwrap();
+        let mut reply = String::new();
+        while let Some(delta) = stream.next().await {
+            reply.push_str(&delta.unwrap());
+        }
+        assert_eq!(reply, "streamed answer");
+        assert_eq!(session.history().len(), 2);
+
+        session.add_assistant_message(&reply);
+        assert_eq!(session.history().last().unwrap().content, "streamed answer");
+    }
+
+    #[tokio::test]
+    async fn condense_query_rewrites_follow_ups_only() {
+        let mut session = mock_session(&[
+            "Staging deploys every Tuesday.",
+            "\"staging rollback approval\"",
+        ]);
+
+        // The first question is searched as typed without calling the model
+        session.add_user_message("When does staging deploy?");
+        assert_eq!(session.condense_query().await.unwrap(), "When does staging deploy?");
+        assert_eq!(session.get_response(0.0).await.unwrap(), "Staging deploys every Tuesday.");
+
+        session.add_user_message("And who approves its rollbacks?");
+        assert_eq!(session.condense_query().await.unwrap(), "staging rollback approval");
+        // Condensing leaves the conversation untouched
+        assert_eq!(session.history().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn reset_keeps_only_system_messages() {
+        let mut session = mock_session(&[]);
+        session.add_user_message("hello");
+        session.get_response(0.0).await.unwrap();
+
+        session.reset_conversation(true);
+        assert_eq!(session.history().len(), 1);
+        assert_eq!(session.history()[0].role, "system");
+
+        session.reset_conversation(false);
+        assert!(session.history().is_empty());
+    }
+
+    #[tokio::test]
+    async fn context_numbers_continue_across_kept_blocks() {
+        let mut session = mock_session(&[]);
+        session.set_max_contexts(2);
+        assert_eq!(session.next_context_number(), 1);
+
+        session.add_user_message("one");
+        session.attach_context("Context 1 ... Context 3", 3);
+        // Re-attaching to the same message replaces its block
+        assert_eq!(session.next_context_number(), 1);
+        session.get_response(0.0).await.unwrap();
+
+        session.add_user_message("two");
+        assert_eq!(session.next_context_number(), 4);
+        session.attach_context("Context 4 ... Context 5", 5);
+        session.get_response(0.0).await.unwrap();
+
+        // Only the latest block is still sent along with a third one
+        session.add_user_message("three");
+        assert_eq!(session.next_context_number(), 6);
+        session.attach_context("Context 6", 6);
+        session.get_response(0.0).await.unwrap();
+
+        session.set_max_contexts(1);
+        session.add_user_message("four");
+        assert_eq!(session.next_context_number(), 1);
+    }
+
+    /// A session holding three earlier turns and a new question, with a context
+    /// window that fits the system prompt, the last earlier turn and the question.
+    fn long_session(responses: &[&str]) -> ChatSession {
+        let mut session = mock_session(responses);
+        for turn in 1..=3 {
+            session.add_user_message(&format!("Question {}: {}", turn, "words ".repeat(40)));
+            session.add_assistant_message(&format!("Answer {}: {}", turn, "words ".repeat(40)));
+        }
+        session.add_user_message("Question 4?");
+
+        let counter = session.token_counter.as_ref().unwrap();
+        let history = session.history();
+        let kept = [&history[..1], &history[5..]].concat();
+        session.context_limit = RESPONSE_TOKEN_RESERVE + counter.count_messages(&kept) + 60;
+        session
+    }
+
+    /// Rough token cost of a short context block.
+    const TOKENS_FOR_CONTEXT: usize = 10;
+
+    fn contents(messages: &[ChatMessage]) -> Vec<String> {
+        messages
+            .iter()
+            .map(|msg| msg.content.split(':').next().unwrap().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn fit_history_drops_oldest_turns_whole() {
+        let mut session = long_session(&[]);
+        session.fit_history().await;
+
+        assert_eq!(
+            contents(session.history()),
+            ["You are a test assistant.", "Question 3", "Answer 3", "Question 4?"]
+        );
+        assert!(session.summary.is_none());
+    }
+
+    #[tokio::test]
+    async fn fit_history_keeps_contexts_with_their_turns() {
+        let mut session = long_session(&[]);
+        session.set_max_contexts(3);
+        // Attach context to questions 1, 3 and 4 (history indices 1, 5 and 7)
+        for (index, context, last_number) in [(1, "Context A", 2), (5, "Context B", 4), (7, "Context C", 6)] {
+            session.contexts.push_back((index, context.to_string(), last_number));
+        }
+        session.context_limit += 2 * TOKENS_FOR_CONTEXT;
+        session.fit_history().await;
+
+        assert_eq!(
+            contents(&session.request_messages()),
+            ["You are a test assistant.", "Question 3", "Context B", "Answer 3", "Question 4?", "Context C"]
+        );
+        assert_eq!(session.contexts.iter().map(|(i, _, _)| *i).collect::<Vec<_>>(), [1, 3]);
+    }
+
+    #[tokio::test]
+    async fn fit_history_summarises_dropped_turns_after_system_messages() {
+        let mut session = long_session(&["Summary: the user asked two questions."]);
+        session.set_history_strategy(HistoryStrategy::Summarize);
+        session.fit_history().await;
+
+        let messages = session.request_messages();
+        assert_eq!(messages[0].content, "You are a test assistant.");
+        assert_eq!(messages[1].role, "system");
+        assert_eq!(
+            messages[1].content,
+            "Summary of the earlier conversation:\nSummary: the user asked two questions."
+        );
+        assert_eq!(messages[2].role, "user");
+        assert_eq!(messages.last().unwrap().content, "Question 4?");
+    }
+
+    #[tokio::test]
+    async fn fit_history_leaves_a_fitting_conversation_alone() {
+        let mut session = long_session(&[]);
+        session.context_limit = 100_000;
+        session.fit_history().await;
+        assert_eq!(session.history().len(), 8);
+    }
+
+    /// Lines are deltas, except `END` which ends the stream and `FAIL` which is an error.
+    fn plain_line(line: &str) -> Result<StreamLine> {
+        match line {
+            "" => Ok(StreamLine::Skip),
+            "END" => Ok(StreamLine::Done),
+            "FAIL" => Err(anyhow!("bad line")),
+            _ => Ok(StreamLine::Delta(line.to_string())),
+        }
+    }
+
+    #[tokio::test]
+    async fn line_deltas_reassemble_characters_split_across_chunks() {
+        // "é" is 0xC3 0xA9 and "🦀" four bytes; both are cut between network chunks
+        let deltas = collect_deltas(&[b"caf\xC3", b"\xA9\n\xF0\x9F", b"\xA6\x80 crab\n\n"], plain_line).await;
+        assert_eq!(deltas, [Ok("café".to_string()), Ok("🦀 crab".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn line_deltas_flush_a_trailing_line_without_newline() {
+        let deltas = collect_deltas(&[b"one\ntw", b"o"], plain_line).await;
+        assert_eq!(deltas, [Ok("one".to_string()), Ok("two".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn line_deltas_stop_at_done_and_after_errors() {
+        let deltas = collect_deltas(&[b"one\nEND\nignored\n"], plain_line).await;
+        assert_eq!(deltas, [Ok("one".to_string())]);
+
+        let deltas = collect_deltas(&[b"one\nFAIL\nignored\n"], plain_line).await;
+        assert_eq!(deltas, [Ok("one".to_string()), Err("bad line".to_string())]);
+    }
+}
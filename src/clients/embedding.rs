@@ -0,0 +1,258 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use reqwest::Client as HttpClient;
+
+use crate::clients::openai::{EmbeddingRequest, EmbeddingResponse, OpenAIClient};
+use crate::config::{
+    DEFAULT_EMBEDDING_MODEL, DEFAULT_HASHING_DIMENSION, EMBEDDING_API_KEY, EMBEDDING_BASE_URL,
+    EMBEDDING_DIMENSION, EMBEDDING_DIMENSIONS, EMBEDDING_PROVIDER,
+};
+
+/// A backend that turns text into embedding vectors.
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    /// Create one embedding vector per input text.
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+
+    /// Size of the vectors produced by this provider.
+    fn dimension(&self) -> usize;
+
+    /// Name of the model stored alongside embedded chunks.
+    fn model_name(&self) -> &str;
+}
+
+#[async_trait]
+impl EmbeddingProvider for OpenAIClient {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        OpenAIClient::embed(self, texts).await
+    }
+
+    fn dimension(&self) -> usize {
+        self.get_embedding_dimension()
+    }
+
+    fn model_name(&self) -> &str {
+        self.get_embedding_model()
+    }
+}
+
+/// Embedder for any server exposing an OpenAI-compatible `/embeddings` endpoint
+/// (Ollama, vLLM, LM Studio, ...).
+pub struct OpenAICompatibleEmbedder {
+    client: HttpClient,
+    base_url: String,
+    api_key: Option<String>,
+    model: String,
+    dimension: usize,
+}
+
+impl OpenAICompatibleEmbedder {
+    /// Initialize an embedder against the given base URL (e.g. `http://localhost:11434/v1`).
+    pub fn new(
+        base_url: Option<String>,
+        api_key: Option<String>,
+        model: Option<String>,
+        dimension: Option<usize>,
+    ) -> Self {
+        let base_url = base_url.unwrap_or_else(|| EMBEDDING_BASE_URL.clone());
+        let model = model.unwrap_or_else(|| DEFAULT_EMBEDDING_MODEL.clone());
+        let dimension = dimension
+            .or(*EMBEDDING_DIMENSION)
+            .or_else(|| EMBEDDING_DIMENSIONS.get(model.as_str()).copied())
+            .unwrap_or(1536);
+
+        Self {
+            client: HttpClient::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: api_key.or_else(|| EMBEDDING_API_KEY.clone()),
+            model,
+            dimension,
+        }
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for OpenAICompatibleEmbedder {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let mut all_vectors = Vec::new();
+        let batch_size = 64;
+
+        for chunk in texts.chunks(batch_size) {
+            let request = EmbeddingRequest {
+                model: self.model.clone(),
+                input: chunk.to_vec(),
+            };
+
+            let mut builder = self.client
+                .post(format!("{}/embeddings", self.base_url))
+                .header("Content-Type", "application/json")
+                .json(&request);
+
+            if let Some(api_key) = &self.api_key {
+                builder = builder.header("Authorization", format!("Bearer {}", api_key));
+            }
+
+            let response = builder.send().await?;
+
+            if !response.status().is_success() {
+                let error_text = response.text().await?;
+                return Err(anyhow!("API error: {}", error_text));
+            }
+
+            let embedding_response: EmbeddingResponse = response.json().await?;
+            all_vectors.extend(embedding_response.data.into_iter().map(|item| item.embedding));
+        }
+
+        Ok(all_vectors)
+    }
+
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn model_name(&self) -> &str {
+        &self.model
+    }
+}
+
+/// Deterministic offline embedder based on feature hashing of word tokens.
+///
+/// The vectors carry no semantic meaning beyond shared vocabulary, but identical
+/// inputs always produce identical vectors, which makes this useful for tests
+/// and for exercising the pipeline without network access.
+pub struct HashingEmbedder {
+    dimension: usize,
+}
+
+impl HashingEmbedder {
+    /// Initialize a hashing embedder producing vectors of the given size.
+    pub fn new(dimension: Option<usize>) -> Self {
+        let dimension = dimension
+            .or(*EMBEDDING_DIMENSION)
+            .unwrap_or(DEFAULT_HASHING_DIMENSION)
+            .max(1);
+        Self { dimension }
+    }
+
+    /// Embed a single text without going through the async trait.
+    pub fn embed_one(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0f32; self.dimension];
+
+        for token in text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|t| !t.is_empty())
+        {
+            let hash = fnv1a(token.to_lowercase().as_bytes());
+            let index = (hash % self.dimension as u64) as usize;
+            // Use a hash bit as the sign so unrelated tokens tend to cancel out
+            let sign = if (hash >> 63) == 0 { 1.0 } else { -1.0 };
+            vector[index] += sign;
+        }
+
+        let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|v| *v /= norm);
+        }
+
+        vector
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for HashingEmbedder {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        Ok(texts.iter().map(|text| self.embed_one(text)).collect())
+    }
+
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn model_name(&self) -> &str {
+        "hashing"
+    }
+}
+
+/// 64-bit FNV-1a hash, stable across platforms and releases.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Create the embedding provider selected by the `EMBEDDING_PROVIDER` setting.
+pub fn create_embedding_provider(model: Option<String>) -> Result<Box<dyn EmbeddingProvider>> {
+    match EMBEDDING_PROVIDER.to_lowercase().as_str() {
+        "openai" => Ok(Box::new(OpenAIClient::new(None, None, model)?)),
+        "openai-compatible" | "compatible" => {
+            Ok(Box::new(OpenAICompatibleEmbedder::new(None, None, model, None)))
+        }
+        "hashing" => Ok(Box::new(HashingEmbedder::new(None))),
+        other => Err(anyhow!(
+            "Unknown embedding provider '{}'. Expected one of: openai, openai-compatible, hashing",
+            other
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn norm(vector: &[f32]) -> f32 {
+        vector.iter().map(|v| v * v).sum::<f32>().sqrt()
+    }
+
+    #[test]
+    fn hashing_embedder_is_deterministic() {
+        let embedder = HashingEmbedder::new(Some(64));
+        let text = "The deploy pipeline runs on Tuesday";
+
+        assert_eq!(embedder.embed_one(text), embedder.embed_one(text));
+        assert_eq!(embedder.embed_one(text), HashingEmbedder::new(Some(64)).embed_one(text));
+        // Tokens are case-insensitive
+        assert_eq!(embedder.embed_one(text), embedder.embed_one(&text.to_uppercase()));
+    }
+
+    #[test]
+    fn hashing_embedder_uses_requested_dimension() {
+        for dimension in [1, 16, 384] {
+            let embedder = HashingEmbedder::new(Some(dimension));
+            assert_eq!(embedder.dimension(), dimension);
+            assert_eq!(embedder.embed_one("some text").len(), dimension);
+        }
+        assert_eq!(HashingEmbedder::new(Some(0)).dimension(), 1);
+    }
+
+    #[test]
+    fn hashing_embedder_normalises_vectors() {
+        let embedder = HashingEmbedder::new(Some(128));
+        for text in ["one", "a few different words here", "repeated repeated repeated"] {
+            assert!((norm(&embedder.embed_one(text)) - 1.0).abs() < 1e-5);
+        }
+        // Text without tokens stays the zero vector instead of dividing by zero
+        assert!(embedder.embed_one(" ... ").iter().all(|v| *v == 0.0));
+    }
+
+    #[test]
+    fn hashing_embedder_relates_shared_vocabulary() {
+        let embedder = HashingEmbedder::new(Some(256));
+        let similarity = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>();
+
+        let query = embedder.embed_one("deploy pipeline");
+        let related = embedder.embed_one("the deploy pipeline failed");
+        let unrelated = embedder.embed_one("lunch menu on friday");
+        assert!(similarity(&query, &related) > similarity(&query, &unrelated));
+    }
+
+    #[tokio::test]
+    async fn hashing_embedder_embeds_batches_in_order() {
+        let embedder = HashingEmbedder::new(Some(32));
+        let texts = vec!["first".to_string(), "second".to_string()];
+
+        let vectors = embedder.embed(&texts).await.unwrap();
+        assert_eq!(vectors.len(), 2);
+        assert_eq!(vectors[0], embedder.embed_one("first"));
+        assert_eq!(vectors[1], embedder.embed_one("second"));
+    }
+}
@@ -0,0 +1,15 @@
+pub mod anthropic;
+pub mod chat;
+pub mod embedding;
+pub mod ollama;
+pub mod openai;
+pub mod reranker;
+pub mod tokens;
+
+pub use anthropic::*;
+pub use chat::*;
+pub use embedding::*;
+pub use ollama::*;
+pub use openai::*;
+pub use reranker::*;
+pub use tokens::*;
\ No newline at end of file
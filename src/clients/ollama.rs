@@ -0,0 +1,169 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use reqwest::Client as HttpClient;
+use serde::{Deserialize, Serialize};
+
+use crate::clients::chat::{line_deltas, ChatMessage, ChatProvider, ChatStream, StreamLine};
+use crate::config::{CHAT_CONTEXT_LIMIT, OLLAMA_BASE_URL, OLLAMA_CHAT_MODEL, OLLAMA_NUM_CTX};
+
+// Ollama API types
+#[derive(Debug, Serialize)]
+struct ChatOptions {
+    temperature: f32,
+    num_ctx: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    stream: bool,
+    options: ChatOptions,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponseMessage {
+    content: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponse {
+    message: Option<ChatResponseMessage>,
+    #[serde(default)]
+    done: bool,
+    error: Option<String>,
+}
+
+/// Client for Ollama's native `/api/chat` endpoint.
+///
+/// Ollama silently cuts prompts to the `num_ctx` the model is loaded with, so
+/// every request asks for the context window the conversation is fitted into.
+pub struct OllamaClient {
+    client: HttpClient,
+    base_url: String,
+    chat_model: String,
+    num_ctx: usize,
+}
+
+impl OllamaClient {
+    /// Initialize Ollama client against the given server (e.g. `http://localhost:11434`).
+    pub fn new(base_url: Option<String>, chat_model: Option<String>) -> Self {
+        let base_url = base_url.unwrap_or_else(|| OLLAMA_BASE_URL.clone());
+
+        Self {
+            client: HttpClient::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            chat_model: chat_model.unwrap_or_else(|| OLLAMA_CHAT_MODEL.clone()),
+            num_ctx: CHAT_CONTEXT_LIMIT.unwrap_or(*OLLAMA_NUM_CTX),
+        }
+    }
+
+    /// Send a chat request and return the raw response.
+    async fn send_request(&self, messages: &[ChatMessage], temperature: f32, stream: bool) -> Result<reqwest::Response> {
+        let request = ChatRequest {
+            model: self.chat_model.clone(),
+            messages: messages.to_vec(),
+            stream,
+            options: ChatOptions {
+                temperature,
+                num_ctx: self.num_ctx,
+            },
+        };
+
+        let response = self.client
+            .post(format!("{}/api/chat", self.base_url))
+            .header("Content-Type", "application/json")
+            .json(&request)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            return Err(anyhow!("API error: {}", error_text));
+        }
+
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl ChatProvider for OllamaClient {
+    async fn complete(&self, messages: &[ChatMessage], temperature: f32) -> Result<String> {
+        let response = self.send_request(messages, temperature, false).await?;
+
+        let chat_response: ChatResponse = response.json().await?;
+        if let Some(error) = chat_response.error {
+            return Err(anyhow!("API error: {}", error));
+        }
+
+        chat_response
+            .message
+            .map(|message| message.content)
+            .ok_or_else(|| anyhow!("No content in response"))
+    }
+
+    async fn stream(&self, messages: &[ChatMessage], temperature: f32) -> Result<ChatStream> {
+        let response = self.send_request(messages, temperature, true).await?;
+
+        Ok(line_deltas(response.bytes_stream(), parse_ndjson_line))
+    }
+
+    fn model_name(&self) -> &str {
+        &self.chat_model
+    }
+
+    fn context_limit(&self) -> usize {
+        self.num_ctx
+    }
+}
+
+/// Parse one line of Ollama's newline-delimited JSON stream.
+fn parse_ndjson_line(line: &str) -> Result<StreamLine> {
+    if line.is_empty() {
+        return Ok(StreamLine::Skip);
+    }
+
+    let chunk: ChatResponse = serde_json::from_str(line)
+        .map_err(|e| anyhow!("Failed to parse stream chunk: {} - Chunk was: {}", e, line))?;
+
+    if let Some(error) = chunk.error {
+        return Err(anyhow!("API error: {}", error));
+    }
+
+    match chunk.message.map(|message| message.content) {
+        Some(content) if !content.is_empty() => Ok(StreamLine::Delta(content)),
+        _ if chunk.done => Ok(StreamLine::Done),
+        _ => Ok(StreamLine::Skip),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::chat::collect_deltas;
+
+    #[test]
+    fn ndjson_lines_yield_content_until_done() {
+        let line = r#"{"message":{"role":"assistant","content":"Hi"},"done":false}"#;
+        assert_eq!(parse_ndjson_line(line).unwrap(), StreamLine::Delta("Hi".to_string()));
+        let last = r#"{"message":{"role":"assistant","content":""},"done":true,"eval_count":3}"#;
+        assert_eq!(parse_ndjson_line(last).unwrap(), StreamLine::Done);
+        assert_eq!(parse_ndjson_line("").unwrap(), StreamLine::Skip);
+
+        let error = r#"{"error":"model 'llama9' not found"}"#;
+        assert!(parse_ndjson_line(error).unwrap_err().to_string().contains("llama9"));
+    }
+
+    #[tokio::test]
+    async fn ndjson_stream_keeps_a_final_line_without_newline() {
+        let deltas = collect_deltas(
+            &[
+                b"{\"message\":{\"content\":\"One \"},\"done\":false}\n{\"message\":",
+                b"{\"content\":\"two\"},\"done\":false}",
+            ],
+            parse_ndjson_line,
+        )
+        .await;
+        assert_eq!(deltas, [Ok("One ".to_string()), Ok("two".to_string())]);
+    }
+}
@@ -0,0 +1,296 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use reqwest::Client as HttpClient;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::clients::chat::{line_deltas, ChatMessage, ChatProvider, ChatStream, StreamLine};
+use crate::config::{
+    DEFAULT_CHAT_MODEL, DEFAULT_EMBEDDING_MODEL, EMBEDDING_DIMENSIONS, OPENAI_API_KEY,
+    OPENAI_BASE_URL, OPENAI_ORG_ID, OPENAI_PROJECT_ID,
+};
+
+// OpenAI API types
+#[derive(Debug, Serialize)]
+struct ChatRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<HashMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponseChoice {
+    message: ChatResponseMessage,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponseMessage {
+    content: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatResponseChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatStreamDelta {
+    content: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatStreamChoice {
+    delta: ChatStreamDelta,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatStreamChunk {
+    choices: Vec<ChatStreamChoice>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct EmbeddingRequest {
+    pub(crate) model: String,
+    pub(crate) input: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct EmbeddingData {
+    pub(crate) embedding: Vec<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct EmbeddingResponse {
+    pub(crate) data: Vec<EmbeddingData>,
+}
+
+/// Client for interacting with OpenAI APIs for both chat completions and embeddings.
+pub struct OpenAIClient {
+    client: HttpClient,
+    base_url: String,
+    api_key: String,
+    org_id: Option<String>,
+    project_id: Option<String>,
+    chat_model: String,
+    embedding_model: String,
+    embedding_dimension: usize,
+}
+
+impl OpenAIClient {
+    /// Initialize OpenAI client for both chat completions and embeddings.
+    ///
+    /// The base URL, organization and project are read from `OPENAI_BASE_URL`,
+    /// `OPENAI_ORG_ID` and `OPENAI_PROJECT_ID`, so the client can target gateways,
+    /// proxies or a local mock server.
+    pub fn new(
+        api_key: Option<String>,
+        chat_model: Option<String>,
+        embedding_model: Option<String>,
+    ) -> Result<Self> {
+        let api_key = api_key.or_else(|| OPENAI_API_KEY.clone());
+        
+        if api_key.is_none() {
+            return Err(anyhow!(
+                "OpenAI API key is required. Set OPENAI_API_KEY environment variable or pass as parameter."
+            ));
+        }
+
+        let client = HttpClient::new();
+        let chat_model = chat_model.unwrap_or_else(|| DEFAULT_CHAT_MODEL.clone());
+        let embedding_model = embedding_model.unwrap_or_else(|| DEFAULT_EMBEDDING_MODEL.clone());
+        
+        // Get embedding dimension based on model
+        let embedding_dimension = EMBEDDING_DIMENSIONS
+            .get(embedding_model.as_str())
+            .copied()
+            .unwrap_or(1536);
+
+        Ok(Self {
+            client,
+            base_url: OPENAI_BASE_URL.trim_end_matches('/').to_string(),
+            api_key: api_key.unwrap(),
+            org_id: OPENAI_ORG_ID.clone(),
+            project_id: OPENAI_PROJECT_ID.clone(),
+            chat_model,
+            embedding_model,
+            embedding_dimension,
+        })
+    }
+
+    /// Create embeddings using OpenAI's embedding model.
+    pub async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let mut all_vectors = Vec::new();
+        let batch_size = 64;
+
+        for chunk in texts.chunks(batch_size) {
+            let request = EmbeddingRequest {
+                model: self.embedding_model.clone(),
+                input: chunk.to_vec(),
+            };
+
+            let response = self
+                .post("embeddings")
+                .json(&request)
+                .send()
+                .await?;
+
+            if !response.status().is_success() {
+                let error_text = response.text().await?;
+                return Err(anyhow!("API error: {}", error_text));
+            }
+
+            let embedding_response: EmbeddingResponse = response.json().await?;
+            let vectors: Vec<Vec<f32>> = embedding_response
+                .data
+                .into_iter()
+                .map(|item| item.embedding)
+                .collect();
+            
+            all_vectors.extend(vectors);
+        }
+
+        Ok(all_vectors)
+    }
+
+    /// Get the embedding dimension for the current model
+    pub fn get_embedding_dimension(&self) -> usize {
+        self.embedding_dimension
+    }
+
+    /// Get the name of the embedding model
+    pub fn get_embedding_model(&self) -> &str {
+        &self.embedding_model
+    }
+
+    /// Build a POST request to an API path with auth and routing headers.
+    fn post(&self, path: &str) -> reqwest::RequestBuilder {
+        let mut builder = self.client
+            .post(format!("{}/{}", self.base_url, path))
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Content-Type", "application/json");
+
+        if let Some(org_id) = &self.org_id {
+            builder = builder.header("OpenAI-Organization", org_id);
+        }
+        if let Some(project_id) = &self.project_id {
+            builder = builder.header("OpenAI-Project", project_id);
+        }
+
+        builder
+    }
+
+    /// Send a chat completion request and return the raw response.
+    async fn send_chat_request(&self, request: &ChatRequest) -> Result<reqwest::Response> {
+        let response = self
+            .post("chat/completions")
+            .json(request)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            return Err(anyhow!("API error: {}", error_text));
+        }
+
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl ChatProvider for OpenAIClient {
+    async fn complete(&self, messages: &[ChatMessage], temperature: f32) -> Result<String> {
+        let request = ChatRequest {
+            model: self.chat_model.clone(),
+            messages: messages.to_vec(),
+            temperature,
+            response_format: None,
+            stream: None,
+        };
+
+        let response = self.send_chat_request(&request).await?;
+
+        let chat_response: ChatResponse = response.json().await?;
+        chat_response
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .ok_or_else(|| anyhow!("No content in response"))
+    }
+
+    async fn stream(&self, messages: &[ChatMessage], temperature: f32) -> Result<ChatStream> {
+        let request = ChatRequest {
+            model: self.chat_model.clone(),
+            messages: messages.to_vec(),
+            temperature,
+            response_format: None,
+            stream: Some(true),
+        };
+
+        let response = self.send_chat_request(&request).await?;
+
+        Ok(line_deltas(response.bytes_stream(), parse_sse_line))
+    }
+
+    fn model_name(&self) -> &str {
+        &self.chat_model
+    }
+}
+
+/// Parse one server-sent event line of a streamed chat completion.
+fn parse_sse_line(line: &str) -> Result<StreamLine> {
+    let data = match line.strip_prefix("data:") {
+        Some(data) => data.trim(),
+        // Comments, event names and blank keep-alive lines carry no content
+        None => return Ok(StreamLine::Skip),
+    };
+
+    if data == "[DONE]" {
+        return Ok(StreamLine::Done);
+    }
+
+    let chunk: ChatStreamChunk = serde_json::from_str(data)
+        .map_err(|e| anyhow!("Failed to parse stream chunk: {} - Chunk was: {}", e, data))?;
+
+    match chunk.choices.into_iter().next().and_then(|choice| choice.delta.content) {
+        Some(content) if !content.is_empty() => Ok(StreamLine::Delta(content)),
+        _ => Ok(StreamLine::Skip),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::chat::collect_deltas;
+
+    #[test]
+    fn sse_lines_yield_content_and_done() {
+        let line = r#"data: {"choices":[{"delta":{"content":"Hi"}}]}"#;
+        assert_eq!(parse_sse_line(line).unwrap(), StreamLine::Delta("Hi".to_string()));
+        assert_eq!(parse_sse_line("data: [DONE]").unwrap(), StreamLine::Done);
+
+        // Role-only deltas, comments and keep-alive lines carry no content
+        let role = r#"data: {"choices":[{"delta":{"role":"assistant"}}]}"#;
+        assert_eq!(parse_sse_line(role).unwrap(), StreamLine::Skip);
+        assert_eq!(parse_sse_line(": keep-alive").unwrap(), StreamLine::Skip);
+        assert_eq!(parse_sse_line("").unwrap(), StreamLine::Skip);
+        assert!(parse_sse_line("data: {not json").is_err());
+    }
+
+    #[tokio::test]
+    async fn sse_stream_stops_at_done() {
+        let deltas = collect_deltas(
+            &[
+                b"data: {\"choices\":[{\"delta\":{\"content\":\"Gr\xC3",
+                b"\xBC\xC3\x9Fe\"}}]}\n\ndata: [DONE]\n\ndata: {\"choices\":[{\"delta\":{\"content\":\"late\"}}]}\n",
+            ],
+            parse_sse_line,
+        )
+        .await;
+        assert_eq!(deltas, [Ok("Grüße".to_string())]);
+    }
+}
@@ -0,0 +1,226 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::Client as HttpClient;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::clients::chat::{create_chat_provider, ChatMessage, ChatProvider};
+use crate::config::{RERANKER, RERANK_API_KEY, RERANK_BASE_URL, RERANK_MODEL};
+use crate::services::sparse::tokenize;
+
+/// A backend that scores how relevant documents are to a query.
+#[async_trait]
+pub trait Reranker: Send + Sync {
+    /// Score each document against the query; higher means more relevant.
+    async fn score(&self, query: &str, documents: &[String]) -> Result<Vec<f32>>;
+
+    /// Name of the reranker, shown in logs.
+    fn name(&self) -> &str;
+}
+
+/// Offline reranker scoring documents by the share of query terms they contain.
+///
+/// Crude compared to a cross-encoder, but free, deterministic and available
+/// without network access.
+pub struct LexicalReranker;
+
+#[async_trait]
+impl Reranker for LexicalReranker {
+    async fn score(&self, query: &str, documents: &[String]) -> Result<Vec<f32>> {
+        let query_terms: HashSet<String> = tokenize(query).into_iter().collect();
+        if query_terms.is_empty() {
+            return Ok(vec![0.0; documents.len()]);
+        }
+
+        Ok(documents
+            .iter()
+            .map(|document| {
+                let document_terms: HashSet<String> = tokenize(document).into_iter().collect();
+                let shared = query_terms.intersection(&document_terms).count();
+                shared as f32 / query_terms.len() as f32
+            })
+            .collect())
+    }
+
+    fn name(&self) -> &str {
+        "lexical"
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct RerankRequest<'a> {
+    model: &'a str,
+    query: &'a str,
+    documents: &'a [String],
+}
+
+#[derive(Debug, Deserialize)]
+struct RerankResult {
+    index: usize,
+    relevance_score: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct RerankResponse {
+    results: Vec<RerankResult>,
+}
+
+/// Cross-encoder reranker for servers exposing a `/rerank` endpoint in the
+/// Cohere/Jina format (Jina, Cohere, Infinity, vLLM, ...).
+pub struct CrossEncoderReranker {
+    client: HttpClient,
+    base_url: String,
+    api_key: Option<String>,
+    model: String,
+}
+
+impl CrossEncoderReranker {
+    /// Initialize a reranker against the given base URL (e.g. `https://api.jina.ai/v1`).
+    pub fn new(base_url: Option<String>, api_key: Option<String>, model: Option<String>) -> Self {
+        let base_url = base_url.unwrap_or_else(|| RERANK_BASE_URL.clone());
+        Self {
+            client: HttpClient::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: api_key.or_else(|| RERANK_API_KEY.clone()),
+            model: model.unwrap_or_else(|| RERANK_MODEL.clone()),
+        }
+    }
+}
+
+#[async_trait]
+impl Reranker for CrossEncoderReranker {
+    async fn score(&self, query: &str, documents: &[String]) -> Result<Vec<f32>> {
+        let request = RerankRequest {
+            model: &self.model,
+            query,
+            documents,
+        };
+
+        let mut builder = self.client
+            .post(format!("{}/rerank", self.base_url))
+            .header("Content-Type", "application/json");
+        if let Some(api_key) = &self.api_key {
+            builder = builder.header("Authorization", format!("Bearer {}", api_key));
+        }
+
+        let response = builder.json(&request).send().await?;
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            return Err(anyhow!("API error: {}", error_text));
+        }
+
+        // Results come back sorted by relevance; put them back in document order
+        let rerank_response: RerankResponse = response.json().await?;
+        let mut scores = vec![f32::MIN; documents.len()];
+        for result in rerank_response.results {
+            if let Some(score) = scores.get_mut(result.index) {
+                *score = result.relevance_score;
+            }
+        }
+        Ok(scores)
+    }
+
+    fn name(&self) -> &str {
+        &self.model
+    }
+}
+
+static SCORE_LIST: Lazy<Regex> = Lazy::new(|| Regex::new(r"\[[^\[\]]*\]").unwrap());
+
+/// Reranker asking a chat model to judge the relevance of each document.
+pub struct LlmReranker {
+    provider: Box<dyn ChatProvider>,
+}
+
+impl LlmReranker {
+    /// Use the given chat provider as the relevance judge.
+    pub fn new(provider: Box<dyn ChatProvider>) -> Self {
+        Self { provider }
+    }
+}
+
+#[async_trait]
+impl Reranker for LlmReranker {
+    async fn score(&self, query: &str, documents: &[String]) -> Result<Vec<f32>> {
+        let passages = documents
+            .iter()
+            .enumerate()
+            .map(|(i, document)| format!("Passage {}:\n{}", i + 1, document))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        let messages = [
+            ChatMessage::new(
+                "system",
+                "You judge how relevant passages are to a search query. \
+                Rate each passage from 0 (irrelevant) to 10 (answers the query directly). \
+                Reply with only a JSON array of numbers, one per passage, in order.",
+            ),
+            ChatMessage::new("user", &format!("Query: {}\n\n{}", query, passages)),
+        ];
+        let reply = self.provider.complete(&messages, 0.0).await?;
+
+        let scores: Vec<f32> = SCORE_LIST
+            .find(&reply)
+            .and_then(|list| serde_json::from_str(list.as_str()).ok())
+            .ok_or_else(|| anyhow!("Relevance judge did not return a list of scores: {}", reply))?;
+        if scores.len() != documents.len() {
+            return Err(anyhow!(
+                "Relevance judge returned {} scores for {} passages",
+                scores.len(),
+                documents.len()
+            ));
+        }
+        Ok(scores)
+    }
+
+    fn name(&self) -> &str {
+        self.provider.model_name()
+    }
+}
+
+/// Create the reranker selected by the `RERANKER` setting, or `None` when disabled.
+pub fn create_reranker() -> Result<Option<Box<dyn Reranker>>> {
+    match RERANKER.to_lowercase().as_str() {
+        "" | "none" | "off" => Ok(None),
+        "lexical" => Ok(Some(Box::new(LexicalReranker))),
+        "cross-encoder" | "cross_encoder" => Ok(Some(Box::new(CrossEncoderReranker::new(None, None, None)))),
+        "llm" => Ok(Some(Box::new(LlmReranker::new(create_chat_provider(None)?)))),
+        other => Err(anyhow!(
+            "Unknown reranker '{}'. Expected one of: none, lexical, cross-encoder, llm",
+            other
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn lexical_scores_are_the_share_of_query_terms() {
+        let documents = vec![
+            "Restart the deploy pipeline".to_string(),
+            "The deploy pipeline failed with E-4711; restart it".to_string(),
+            "Lunch menu for Tuesday".to_string(),
+        ];
+        let scores = LexicalReranker
+            .score("How do I restart the deploy pipeline after E-4711?", &documents)
+            .await
+            .unwrap();
+
+        assert!(scores[1] > scores[0]);
+        assert!(scores[0] > scores[2]);
+        assert_eq!(scores[2], 0.0);
+        assert!(scores.iter().all(|score| (0.0..=1.0).contains(score)));
+    }
+
+    #[tokio::test]
+    async fn lexical_scores_are_zero_without_query_terms() {
+        let documents = vec!["anything".to_string(), "else".to_string()];
+        let scores = LexicalReranker.score("what is the", &documents).await.unwrap();
+        assert_eq!(scores, [0.0, 0.0]);
+    }
+}
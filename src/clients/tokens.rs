@@ -0,0 +1,57 @@
+use anyhow::Result;
+use tiktoken_rs::{cl100k_base, get_bpe_from_model, CoreBPE};
+
+use crate::clients::chat::ChatMessage;
+use crate::config::{CHAT_CONTEXT_LIMIT, CHAT_CONTEXT_LIMITS, DEFAULT_CHAT_CONTEXT_LIMIT};
+
+/// Tokens added by the chat format around every message.
+const TOKENS_PER_MESSAGE: usize = 4;
+/// Tokens priming the assistant's reply.
+const TOKENS_PER_REPLY: usize = 3;
+
+/// Counts the tokens a conversation takes up in a chat model's context window.
+///
+/// Uses the model's tiktoken encoding where known and `cl100k_base` otherwise,
+/// so counts for non-OpenAI models are estimates.
+pub struct TokenCounter {
+    bpe: CoreBPE,
+}
+
+impl TokenCounter {
+    /// Create a counter for the given chat model.
+    pub fn new(model_name: &str) -> Result<Self> {
+        let bpe = get_bpe_from_model(model_name).or_else(|_| cl100k_base())?;
+        Ok(Self { bpe })
+    }
+
+    /// Count the tokens of a piece of text.
+    pub fn count_text(&self, text: &str) -> usize {
+        self.bpe.encode_ordinary(text).len()
+    }
+
+    /// Count the tokens of a list of messages, including the chat format overhead.
+    pub fn count_messages(&self, messages: &[ChatMessage]) -> usize {
+        messages
+            .iter()
+            .map(|msg| TOKENS_PER_MESSAGE + self.count_text(&msg.role) + self.count_text(&msg.content))
+            .sum::<usize>()
+            + TOKENS_PER_REPLY
+    }
+}
+
+/// Context window size of a chat model, in tokens.
+///
+/// `CHAT_CONTEXT_LIMIT` overrides the table; unknown models get a conservative default.
+pub fn context_limit(model_name: &str) -> usize {
+    if let Some(limit) = *CHAT_CONTEXT_LIMIT {
+        return limit;
+    }
+
+    // Longest matching prefix, so "gpt-4o-mini-2024-07-18" finds "gpt-4o-mini"
+    CHAT_CONTEXT_LIMITS
+        .iter()
+        .filter(|(model, _)| model_name.starts_with(*model))
+        .max_by_key(|(model, _)| model.len())
+        .map(|(_, limit)| *limit)
+        .unwrap_or(DEFAULT_CHAT_CONTEXT_LIMIT)
+}
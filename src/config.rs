@@ -5,6 +5,20 @@ use once_cell::sync::Lazy;
 
 // API Keys
 pub static OPENAI_API_KEY: Lazy<Option<String>> = Lazy::new(|| env::var("OPENAI_API_KEY").ok());
+pub static OPENAI_BASE_URL: Lazy<String> = Lazy::new(|| env::var("OPENAI_BASE_URL").unwrap_or_else(|_| "https://api.openai.com/v1".to_string()));
+pub static OPENAI_ORG_ID: Lazy<Option<String>> = Lazy::new(|| env::var("OPENAI_ORG_ID").ok());
+pub static OPENAI_PROJECT_ID: Lazy<Option<String>> = Lazy::new(|| env::var("OPENAI_PROJECT_ID").ok());
+pub static ANTHROPIC_API_KEY: Lazy<Option<String>> = Lazy::new(|| env::var("ANTHROPIC_API_KEY").ok());
+
+// Chat provider settings
+// Supported providers: "openai", "anthropic", "ollama" and "mock" (scripted responses)
+pub static CHAT_PROVIDER: Lazy<String> = Lazy::new(|| env::var("CHAT_PROVIDER").unwrap_or_else(|_| "openai".to_string()));
+pub static ANTHROPIC_BASE_URL: Lazy<String> = Lazy::new(|| env::var("ANTHROPIC_BASE_URL").unwrap_or_else(|_| "https://api.anthropic.com".to_string()));
+pub static ANTHROPIC_MAX_TOKENS: Lazy<u32> = Lazy::new(|| env::var("ANTHROPIC_MAX_TOKENS").ok().and_then(|t| t.parse().ok()).unwrap_or(4096));
+pub static OLLAMA_BASE_URL: Lazy<String> = Lazy::new(|| env::var("OLLAMA_BASE_URL").unwrap_or_else(|_| "http://localhost:11434".to_string()));
+pub static OLLAMA_CHAT_MODEL: Lazy<String> = Lazy::new(|| env::var("OLLAMA_CHAT_MODEL").unwrap_or_else(|_| "llama3.1".to_string()));
+pub static OLLAMA_NUM_CTX: Lazy<usize> = Lazy::new(|| env::var("OLLAMA_NUM_CTX").ok().and_then(|n| n.parse().ok()).unwrap_or(8192));
+pub static MOCK_CHAT_SCRIPT: Lazy<Option<String>> = Lazy::new(|| env::var("MOCK_CHAT_SCRIPT").ok());
 
 // Qdrant settings
 pub static QDRANT_URL: Lazy<String> = Lazy::new(|| env::var("QDRANT_URL").unwrap_or_else(|_| "http://localhost:6333".to_string()));
@@ -15,6 +29,13 @@ pub static QDRANT_COLLECTION: Lazy<String> = Lazy::new(|| env::var("QDRANT_COLLE
 pub static DEFAULT_CHAT_MODEL: Lazy<String> = Lazy::new(|| env::var("DEFAULT_CHAT_MODEL").unwrap_or_else(|_| "gpt-4o".to_string()));
 pub static DEFAULT_EMBEDDING_MODEL: Lazy<String> = Lazy::new(|| env::var("DEFAULT_EMBEDDING_MODEL").unwrap_or_else(|_| "text-embedding-3-small".to_string()));
 
+// Embedding provider settings
+// Supported providers: "openai", "openai-compatible" (Ollama, vLLM, LM Studio, ...) and "hashing" (offline)
+pub static EMBEDDING_PROVIDER: Lazy<String> = Lazy::new(|| env::var("EMBEDDING_PROVIDER").unwrap_or_else(|_| "openai".to_string()));
+pub static EMBEDDING_BASE_URL: Lazy<String> = Lazy::new(|| env::var("EMBEDDING_BASE_URL").unwrap_or_else(|_| "http://localhost:11434/v1".to_string()));
+pub static EMBEDDING_API_KEY: Lazy<Option<String>> = Lazy::new(|| env::var("EMBEDDING_API_KEY").ok());
+pub static EMBEDDING_DIMENSION: Lazy<Option<usize>> = Lazy::new(|| env::var("EMBEDDING_DIMENSION").ok().and_then(|d| d.parse().ok()));
+
 // Available embedding models
 pub static AVAILABLE_EMBEDDING_MODELS: Lazy<Vec<&'static str>> = Lazy::new(|| vec![
     "text-embedding-3-small",
@@ -31,6 +52,41 @@ pub static EMBEDDING_DIMENSIONS: Lazy<HashMap<&'static str, usize>> = Lazy::new(
     map
 });
 
+// Context window sizes (in tokens) by chat model; versioned names such as
+// "gpt-4o-2024-08-06" or "claude-3-5-sonnet-20241022" match by prefix
+pub static CHAT_CONTEXT_LIMITS: Lazy<HashMap<&'static str, usize>> = Lazy::new(|| {
+    let mut map = HashMap::new();
+    map.insert("gpt-4o", 128_000);
+    map.insert("gpt-4o-mini", 128_000);
+    map.insert("gpt-4-turbo", 128_000);
+    map.insert("gpt-4", 8_192);
+    map.insert("gpt-4.1", 1_047_576);
+    map.insert("gpt-3.5-turbo", 16_385);
+    map.insert("o1", 200_000);
+    map.insert("o3-mini", 200_000);
+    map.insert("claude-3", 200_000);
+    map.insert("claude-3-5", 200_000);
+    map.insert("claude-3-7", 200_000);
+    map.insert("claude-sonnet-4", 200_000);
+    map.insert("claude-opus-4", 200_000);
+    map.insert("llama3", 8_192);
+    map.insert("llama3.1", 131_072);
+    map.insert("mistral", 32_768);
+    map.insert("qwen2.5", 32_768);
+    map
+});
+pub const DEFAULT_CHAT_CONTEXT_LIMIT: usize = 8_192;
+pub static CHAT_CONTEXT_LIMIT: Lazy<Option<usize>> = Lazy::new(|| env::var("CHAT_CONTEXT_LIMIT").ok().and_then(|l| l.parse().ok()));
+
+// History budgeting: tokens kept free for the reply, and what happens to turns
+// that no longer fit ("truncate" drops them, "summarize" condenses them)
+pub const RESPONSE_TOKEN_RESERVE: usize = 1_024;
+pub const SUMMARY_TOKEN_BUDGET: usize = 512;
+pub static HISTORY_STRATEGY: Lazy<String> = Lazy::new(|| env::var("HISTORY_STRATEGY").unwrap_or_else(|_| "truncate".to_string()));
+
+// Vector size used by the offline hashing embedder when EMBEDDING_DIMENSION is not set
+pub const DEFAULT_HASHING_DIMENSION: usize = 384;
+
 // Emoji indicators for different information sources
 pub const EMOJI_SEARCH: &str = "🔍";  // Searching
 pub const EMOJI_CONTEXT: &str = "📚";  // Using context from Qdrant
@@ -39,6 +95,38 @@ pub const EMOJI_ERROR: &str = "⚠️";  // Error indicator
 
 // Default chunking settings
 pub const DEFAULT_MAX_SENTENCES_PER_CHUNK: usize = 3;
+pub const DEFAULT_MAX_TOKENS_PER_CHUNK: usize = 512;
+pub const DEFAULT_OVERLAP_TOKENS_PER_CHUNK: usize = 64;
+pub const DEFAULT_MAX_CHARS_PER_SECTION_CHUNK: usize = 2000;
+pub const DEFAULT_MAX_CHARS_PER_CODE_CHUNK: usize = 4000;
+
+// Hybrid retrieval settings (opt-in, since keyword matches bypass the score threshold)
+pub static HYBRID_SEARCH: Lazy<bool> = Lazy::new(|| env::var("HYBRID_SEARCH").map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes" | "on")).unwrap_or(false));
+pub const SPARSE_VECTOR_NAME: &str = "bm25";
+pub const BM25_K1: f32 = 1.2;
+pub const BM25_B: f32 = 0.75;
+pub const BM25_AVG_DOC_LENGTH: f32 = 256.0;
+pub const RRF_K: f32 = 60.0;
+
+// Reranking settings
+pub static RERANKER: Lazy<String> = Lazy::new(|| env::var("RERANKER").unwrap_or_else(|_| "none".to_string()));
+pub static RERANK_BASE_URL: Lazy<String> = Lazy::new(|| env::var("RERANK_BASE_URL").unwrap_or_else(|_| "http://localhost:7997".to_string()));
+pub static RERANK_API_KEY: Lazy<Option<String>> = Lazy::new(|| env::var("RERANK_API_KEY").ok());
+pub static RERANK_MODEL: Lazy<String> = Lazy::new(|| env::var("RERANK_MODEL").unwrap_or_else(|_| "BAAI/bge-reranker-v2-m3".to_string()));
+pub static RERANK_FETCH_K: Lazy<u64> = Lazy::new(|| env::var("RERANK_FETCH_K").ok().and_then(|k| k.parse().ok()).unwrap_or(20));
+
+// Diversity settings (maximal marginal relevance; unset disables it)
+pub static MMR_LAMBDA: Lazy<Option<f32>> = Lazy::new(|| env::var("MMR_LAMBDA").ok().and_then(|l| l.parse().ok()).map(|l: f32| l.clamp(0.0, 1.0)));
+
+// Number of recent turns whose retrieved context is resent with each request
+pub static KEEP_CONTEXTS: Lazy<usize> = Lazy::new(|| env::var("KEEP_CONTEXTS").ok().and_then(|n| n.parse().ok()).unwrap_or(1).max(1));
+
+// Query rewriting: condense follow-up questions into standalone search queries
+pub static QUERY_REWRITE: Lazy<bool> = Lazy::new(|| env::var("QUERY_REWRITE").map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes" | "on")).unwrap_or(false));
+pub const QUERY_REWRITE_HISTORY: usize = 6;
+
+// Neighbour expansion: adjacent chunks merged around each hit (0 disables it)
+pub static CONTEXT_NEIGHBOURS: Lazy<u64> = Lazy::new(|| env::var("CONTEXT_NEIGHBOURS").ok().and_then(|n| n.parse().ok()).unwrap_or(0));
 
 // Text file extensions for auto-detection
 pub static TEXT_FILE_EXTENSIONS: Lazy<Vec<&'static str>> = Lazy::new(|| vec![
@@ -46,19 +134,29 @@ pub static TEXT_FILE_EXTENSIONS: Lazy<Vec<&'static str>> = Lazy::new(|| vec![
     ".md",
     ".py",
     ".js",
+    ".rs",
     ".html",
+    ".htm",
     ".css",
     ".json",
     ".csv",
+    ".jsonl",
+    ".ndjson",
     ".xml",
     ".yaml",
     ".yml",
+    ".pdf",
 ]);
 
 /// Validate that required environment variables are set.
 pub fn validate_environment() -> bool {
-    if OPENAI_API_KEY.is_none() {
-        error!("OPENAI_API_KEY environment variable is not set");
+    // Only the OpenAI embedding provider strictly needs an OpenAI key to embed
+    if OPENAI_API_KEY.is_none() && EMBEDDING_PROVIDER.to_lowercase() == "openai" {
+        error!(
+            "OPENAI_API_KEY environment variable is not set. It is needed by the default \
+            EMBEDDING_PROVIDER=openai; to run without OpenAI (e.g. with Ollama) set \
+            EMBEDDING_PROVIDER=openai-compatible or EMBEDDING_PROVIDER=hashing"
+        );
         return false;
     }
 
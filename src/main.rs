@@ -3,7 +3,7 @@ use log::{error};
 use std::process;
 
 use vector_chat::cli::chat::run_chat;
-use vector_chat::cli::embed::run_embed;
+use vector_chat::cli::embed::{run_embed, EmbedArgs};
 
 /// Vector Chat - Text embedding and chat with context
 #[derive(Parser)]
@@ -16,21 +16,9 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Embed text into vector database
-    Embed {
-        /// Path to text file to embed
-        #[clap(short, long)]
-        file: Option<String>,
+    Embed(Box<EmbedArgs>),
 
-        /// Text to embed directly
-        #[clap(short, long)]
-        text: Option<String>,
-
-        /// List available text files
-        #[clap(short = 'l', long)]
-        list_files: bool,
-    },
-
-    /// Chat with OpenAI using vector context
+    /// Chat with an LLM using vector context
     Chat {
         /// Disable context retrieval
         #[clap(long)]
@@ -53,8 +41,8 @@ async fn main() -> anyhow::Result<()> {
 
     // Run command
     match cli.command {
-        Commands::Embed { file, text, list_files } => {
-            match run_embed(file, text, list_files).await {
+        Commands::Embed(args) => {
+            match run_embed(*args).await {
                 Ok(_) => (),
                 Err(e) => {
                     error!("Error running embed command: {}", e);
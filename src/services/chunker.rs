@@ -1,14 +1,651 @@
-use anyhow::Result;
-use log::info;
+use anyhow::{anyhow, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use regex::Regex;
 use serde_json::Value;
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
+use std::time::UNIX_EPOCH;
+use tiktoken_rs::{cl100k_base, get_bpe_from_model, CoreBPE};
 
-use crate::config::TEXT_FILE_EXTENSIONS;
+use crate::config::{DEFAULT_MAX_CHARS_PER_CODE_CHUNK, TEXT_FILE_EXTENSIONS};
+use crate::services::loader::{load_document, DocumentPart, RecordTemplate};
+use crate::services::qdrant_service::content_hash;
 
-/// Split text into chunks of sentences.
-pub fn chunk_by_sentences(text: &str, max_sents: usize) -> Vec<String> {
+/// A piece of text produced by a chunker, with chunker-specific metadata.
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    pub text: String,
+    pub metadata: HashMap<String, Value>,
+}
+
+impl Chunk {
+    /// Create a chunk without extra metadata.
+    pub fn new(text: String) -> Self {
+        Self {
+            text,
+            metadata: HashMap::new(),
+        }
+    }
+}
+
+/// Strategy for splitting a document into chunks.
+pub trait Chunker: Send + Sync {
+    /// Split text into chunks.
+    fn chunk(&self, text: &str) -> Vec<Chunk>;
+
+    /// Name of the strategy, stored alongside each chunk.
+    fn name(&self) -> &'static str;
+}
+
+/// Chunker grouping a fixed number of sentences per chunk.
+pub struct SentenceChunker {
+    max_sentences: usize,
+}
+
+impl SentenceChunker {
+    /// Create a chunker with at most `max_sentences` sentences per chunk.
+    pub fn new(max_sentences: usize) -> Self {
+        Self {
+            max_sentences: max_sentences.max(1),
+        }
+    }
+}
+
+impl Chunker for SentenceChunker {
+    fn chunk(&self, text: &str) -> Vec<Chunk> {
+        chunk_by_sentences(text, self.max_sentences)
+            .into_iter()
+            .map(Chunk::new)
+            .collect()
+    }
+
+    fn name(&self) -> &'static str {
+        "sentence"
+    }
+}
+
+/// Chunker packing whole sentences into chunks of at most `max_tokens` tokens,
+/// repeating up to `overlap_tokens` tokens of trailing sentences in the next chunk.
+///
+/// Sentences longer than the budget are split on token boundaries.
+pub struct TokenChunker {
+    bpe: CoreBPE,
+    max_tokens: usize,
+    overlap_tokens: usize,
+}
+
+impl TokenChunker {
+    /// Create a chunker using the tokenizer of the given embedding model.
+    ///
+    /// Models unknown to the tokenizer table fall back to `cl100k_base`.
+    pub fn new(model_name: &str, max_tokens: usize, overlap_tokens: usize) -> Result<Self> {
+        let max_tokens = max_tokens.max(1);
+        if overlap_tokens >= max_tokens {
+            return Err(anyhow!(
+                "Overlap ({} tokens) must be smaller than the chunk size ({} tokens)",
+                overlap_tokens,
+                max_tokens
+            ));
+        }
+
+        let bpe = get_bpe_from_model(model_name).or_else(|_| cl100k_base())?;
+
+        Ok(Self {
+            bpe,
+            max_tokens,
+            overlap_tokens,
+        })
+    }
+
+    /// Count the tokens of a piece of text.
+    pub fn count_tokens(&self, text: &str) -> usize {
+        self.bpe.encode_ordinary(text).len()
+    }
+
+    /// Split an oversized sentence into windows of at most `max_tokens` tokens.
+    ///
+    /// Windows are shrunk until they decode to valid UTF-8, so characters spanning
+    /// several tokens are never cut. A character needing more tokens than the
+    /// budget is kept whole.
+    fn split_long_sentence(&self, sentence: &str) -> Vec<(String, usize)> {
+        let tokens = self.bpe.encode_ordinary(sentence);
+        let decode = |start: usize, end: usize| self.bpe.decode(tokens[start..end].to_vec()).ok();
+        let step = self.max_tokens - self.overlap_tokens;
+
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        while start < tokens.len() {
+            let mut end = (start + self.max_tokens).min(tokens.len());
+            let mut piece = decode(start, end);
+            while piece.is_none() && end > start + 1 {
+                end -= 1;
+                piece = decode(start, end);
+            }
+            while piece.is_none() && end < tokens.len() {
+                end += 1;
+                piece = decode(start, end);
+            }
+            match piece {
+                Some(piece) => pieces.push((piece.trim().to_string(), end - start)),
+                None => break,
+            }
+
+            if end == tokens.len() {
+                break;
+            }
+            // The overlap must also start on a character boundary
+            let mut next = (start + step).min(end);
+            while next < end && decode(next, end).is_none() {
+                next = if next > start + 1 { next - 1 } else { end };
+            }
+            start = next;
+        }
+        pieces
+    }
+}
+
+impl Chunker for TokenChunker {
+    fn chunk(&self, text: &str) -> Vec<Chunk> {
+        let mut chunks = Vec::new();
+        // Sentences of the chunk being built, with their token counts
+        let mut current: Vec<(String, usize)> = Vec::new();
+        let mut current_tokens = 0;
+        // Whether `current` holds sentences beyond the overlap of the previous chunk
+        let mut has_new = false;
+
+        for sentence in split_sentences(text) {
+            let tokens = self.count_tokens(&sentence);
+
+            if current_tokens + tokens > self.max_tokens && has_new {
+                chunks.push(token_chunk(&current, current_tokens));
+
+                // Carry trailing sentences into the next chunk as overlap, as long
+                // as they leave room for the incoming sentence
+                let budget = self.overlap_tokens.min(self.max_tokens.saturating_sub(tokens));
+                let mut overlap_tokens = 0;
+                let keep = current
+                    .iter()
+                    .rev()
+                    .take_while(|(_, t)| {
+                        overlap_tokens += t;
+                        overlap_tokens <= budget
+                    })
+                    .count();
+                current.drain(..current.len() - keep);
+                current_tokens = current.iter().map(|(_, t)| t).sum();
+                has_new = false;
+            }
+
+            if tokens > self.max_tokens {
+                if has_new {
+                    chunks.push(token_chunk(&current, current_tokens));
+                }
+                current.clear();
+                current_tokens = 0;
+                has_new = false;
+
+                for (piece, piece_tokens) in self.split_long_sentence(&sentence) {
+                    chunks.push(token_chunk(&[(piece, piece_tokens)], piece_tokens));
+                }
+                continue;
+            }
+
+            current.push((sentence, tokens));
+            current_tokens += tokens;
+            has_new = true;
+        }
+
+        if has_new {
+            chunks.push(token_chunk(&current, current_tokens));
+        }
+
+        chunks
+    }
+
+    fn name(&self) -> &'static str {
+        "token"
+    }
+}
+
+/// Build a chunk from sentences, recording its token count.
+fn token_chunk(sentences: &[(String, usize)], token_count: usize) -> Chunk {
+    let text = sentences
+        .iter()
+        .map(|(sentence, _)| sentence.as_str())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut chunk = Chunk::new(text);
+    chunk.metadata.insert("token_count".to_string(), Value::Number(token_count.into()));
+    chunk
+}
+
+/// Chunker following the structure of Markdown documents.
+///
+/// Sections are split on headings and each chunk records its heading breadcrumb
+/// (e.g. `Setup > Docker`) in the `section` field. Within a section, blocks are
+/// packed up to `max_chars` characters. Oversized fenced code blocks are split
+/// between lines, each piece wrapped in the original fences; a single line longer
+/// than the limit is kept whole.
+pub struct MarkdownChunker {
+    max_chars: usize,
+}
+
+/// A block of a Markdown section: a paragraph, list or fenced code block.
+struct MarkdownBlock {
+    text: String,
+    is_code: bool,
+}
+
+impl MarkdownChunker {
+    /// Create a chunker packing at most `max_chars` characters per chunk.
+    pub fn new(max_chars: usize) -> Self {
+        Self {
+            max_chars: max_chars.max(1),
+        }
+    }
+
+    /// Pack the blocks of one section into chunks.
+    fn chunk_section(&self, breadcrumb: &[(usize, String)], blocks: Vec<MarkdownBlock>, chunks: &mut Vec<Chunk>) {
+        let section = breadcrumb
+            .iter()
+            .map(|(_, title)| title.as_str())
+            .collect::<Vec<_>>()
+            .join(" > ");
+
+        let mut pieces = Vec::new();
+        for block in blocks {
+            if block.text.chars().count() <= self.max_chars {
+                pieces.push(block.text);
+            } else if block.is_code {
+                pieces.extend(self.split_code_block(&block.text));
+            } else {
+                // Oversized prose is split on sentence boundaries
+                pieces.extend(split_sentences(&block.text));
+            }
+        }
+
+        let mut current = String::new();
+        for piece in pieces {
+            // Keep a heading together with the block that follows it
+            let heading_only = !current.contains('\n') && parse_markdown_heading(&current).is_some();
+            if !current.is_empty()
+                && !heading_only
+                && current.chars().count() + piece.chars().count() + 2 > self.max_chars
+            {
+                chunks.push(markdown_chunk(std::mem::take(&mut current), &section));
+            }
+            if !current.is_empty() {
+                current.push_str("\n\n");
+            }
+            current.push_str(&piece);
+        }
+        if !current.trim().is_empty() {
+            chunks.push(markdown_chunk(current, &section));
+        }
+    }
+
+    /// Split a fenced code block between lines into pieces of at most `max_chars`
+    /// characters, repeating the fences around each piece.
+    fn split_code_block(&self, block: &str) -> Vec<String> {
+        let lines: Vec<&str> = block.lines().collect();
+        let opening = lines[0];
+        let (body, closing) = match lines.split_last() {
+            Some((last, body)) if lines.len() > 1 && markdown_fence(last).is_some() => (&body[1..], last.to_string()),
+            // An unterminated fence is closed in every piece
+            _ => (&lines[1..], markdown_fence(opening).unwrap_or_default()),
+        };
+
+        let budget = self.max_chars.saturating_sub(opening.chars().count() + closing.chars().count() + 2);
+        let wrap = |lines: &[&str]| format!("{}\n{}\n{}", opening, lines.join("\n"), closing);
+
+        let mut pieces = Vec::new();
+        let mut current: Vec<&str> = Vec::new();
+        let mut current_chars = 0;
+        for line in body {
+            let line_chars = line.chars().count() + 1;
+            if !current.is_empty() && current_chars + line_chars > budget {
+                pieces.push(wrap(&current));
+                current.clear();
+                current_chars = 0;
+            }
+            current.push(line);
+            current_chars += line_chars;
+        }
+        if !current.is_empty() || pieces.is_empty() {
+            pieces.push(wrap(&current));
+        }
+        pieces
+    }
+}
+
+/// Build a Markdown chunk tagged with its section breadcrumb.
+fn markdown_chunk(text: String, section: &str) -> Chunk {
+    let mut chunk = Chunk::new(text);
+    if !section.is_empty() {
+        chunk.metadata.insert("section".to_string(), Value::String(section.to_string()));
+    }
+    chunk
+}
+
+/// Parse an ATX heading (`## Title`) into its level and title.
+fn parse_markdown_heading(line: &str) -> Option<(usize, String)> {
+    let trimmed = line.trim_start();
+    // Headings may be indented by at most three spaces
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+
+    let level = trimmed.chars().take_while(|c| *c == '#').count();
+    if level == 0 || level > 6 {
+        return None;
+    }
+
+    let rest = &trimmed[level..];
+    if !rest.is_empty() && !rest.starts_with(' ') && !rest.starts_with('\t') {
+        return None;
+    }
+
+    let title = rest.trim().trim_end_matches('#').trim().to_string();
+    Some((level, title))
+}
+
+/// Return the fence marker (e.g. ```` ``` ```` or `~~~~`) opening or closing a code block.
+fn markdown_fence(line: &str) -> Option<String> {
+    let trimmed = line.trim_start();
+    let fence_char = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let length = trimmed.chars().take_while(|c| *c == fence_char).count();
+    if length >= 3 {
+        Some(fence_char.to_string().repeat(length))
+    } else {
+        None
+    }
+}
+
+impl Chunker for MarkdownChunker {
+    fn chunk(&self, text: &str) -> Vec<Chunk> {
+        let mut chunks = Vec::new();
+        let mut breadcrumb: Vec<(usize, String)> = Vec::new();
+        let mut blocks: Vec<MarkdownBlock> = Vec::new();
+        let mut current: Vec<&str> = Vec::new();
+        let mut open_fence: Option<String> = None;
+
+        let end_block = |current: &mut Vec<&str>, blocks: &mut Vec<MarkdownBlock>, is_code: bool| {
+            let block = current.join("\n");
+            if !block.trim().is_empty() {
+                blocks.push(MarkdownBlock {
+                    text: block.trim_end().to_string(),
+                    is_code,
+                });
+            }
+            current.clear();
+        };
+
+        for line in text.lines() {
+            if let Some(fence) = &open_fence {
+                current.push(line);
+                let closes = markdown_fence(line)
+                    .map(|marker| marker.starts_with(fence.as_str()) && line.trim().trim_start_matches(marker.as_str()).is_empty())
+                    .unwrap_or(false);
+                if closes {
+                    open_fence = None;
+                    end_block(&mut current, &mut blocks, true);
+                }
+                continue;
+            }
+
+            if let Some(fence) = markdown_fence(line) {
+                end_block(&mut current, &mut blocks, false);
+                current.push(line);
+                open_fence = Some(fence);
+                continue;
+            }
+
+            if let Some((level, title)) = parse_markdown_heading(line) {
+                end_block(&mut current, &mut blocks, false);
+                // A section holding nothing but its heading is covered by the breadcrumbs of its subsections
+                if blocks.len() > 1 || breadcrumb.is_empty() {
+                    self.chunk_section(&breadcrumb, std::mem::take(&mut blocks), &mut chunks);
+                }
+                blocks.clear();
+
+                breadcrumb.retain(|(l, _)| *l < level);
+                breadcrumb.push((level, title));
+                current.push(line);
+                end_block(&mut current, &mut blocks, false);
+                continue;
+            }
+
+            if line.trim().is_empty() {
+                end_block(&mut current, &mut blocks, false);
+            } else {
+                current.push(line);
+            }
+        }
+
+        // An unterminated fence still counts as code
+        let is_code = open_fence.is_some();
+        end_block(&mut current, &mut blocks, is_code);
+        self.chunk_section(&breadcrumb, blocks, &mut chunks);
+
+        chunks
+    }
+
+    fn name(&self) -> &'static str {
+        "markdown"
+    }
+}
+
+/// Programming languages understood by the code chunker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    Python,
+    JavaScript,
+    Rust,
+}
+
+/// Patterns recognising top-level definitions, as (kind, regex with a `name` group).
+type DefinitionPatterns = Vec<(&'static str, Regex)>;
+
+static PYTHON_DEFINITIONS: Lazy<DefinitionPatterns> = Lazy::new(|| vec![
+    ("function", Regex::new(r"^(?:async\s+)?def\s+(?P<name>[A-Za-z_]\w*)").unwrap()),
+    ("class", Regex::new(r"^class\s+(?P<name>[A-Za-z_]\w*)").unwrap()),
+]);
+
+static JAVASCRIPT_DEFINITIONS: Lazy<DefinitionPatterns> = Lazy::new(|| vec![
+    ("function", Regex::new(r"^(?:export\s+)?(?:default\s+)?(?:async\s+)?function\s*\*?\s*(?P<name>[A-Za-z_$][\w$]*)").unwrap()),
+    ("class", Regex::new(r"^(?:export\s+)?(?:default\s+)?class\s+(?P<name>[A-Za-z_$][\w$]*)").unwrap()),
+    ("function", Regex::new(r"^(?:export\s+)?(?:const|let|var)\s+(?P<name>[A-Za-z_$][\w$]*)\s*=\s*(?:async\s+)?(?:function\b|\([^)]*\)\s*=>|[A-Za-z_$][\w$]*\s*=>)").unwrap()),
+]);
+
+static RUST_DEFINITIONS: Lazy<DefinitionPatterns> = Lazy::new(|| vec![
+    ("fn", Regex::new(r#"^(?:pub(?:\([^)]*\))?\s+)?(?:(?:const|async|unsafe|extern\s+"[^"]*")\s+)*fn\s+(?P<name>[A-Za-z_]\w*)"#).unwrap()),
+    ("struct", Regex::new(r"^(?:pub(?:\([^)]*\))?\s+)?struct\s+(?P<name>[A-Za-z_]\w*)").unwrap()),
+    ("enum", Regex::new(r"^(?:pub(?:\([^)]*\))?\s+)?enum\s+(?P<name>[A-Za-z_]\w*)").unwrap()),
+    ("trait", Regex::new(r"^(?:pub(?:\([^)]*\))?\s+)?(?:unsafe\s+)?trait\s+(?P<name>[A-Za-z_]\w*)").unwrap()),
+    ("impl", Regex::new(r"^(?:unsafe\s+)?impl(?:<[^{]*?>)?\s+(?P<name>[^{]+?)\s*(?:\{|where\b|$)").unwrap()),
+    ("mod", Regex::new(r"^(?:pub(?:\([^)]*\))?\s+)?mod\s+(?P<name>[A-Za-z_]\w*)\s*\{").unwrap()),
+    ("macro", Regex::new(r"^macro_rules!\s*(?P<name>[A-Za-z_]\w*)").unwrap()),
+]);
+
+impl Language {
+    /// Detect the language of a file from its extension.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_string_lossy().to_lowercase();
+        match extension.as_str() {
+            "py" => Some(Language::Python),
+            "js" | "mjs" | "cjs" | "jsx" => Some(Language::JavaScript),
+            "rs" => Some(Language::Rust),
+            _ => None,
+        }
+    }
+
+    /// Name stored in the chunk payload.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Language::Python => "python",
+            Language::JavaScript => "javascript",
+            Language::Rust => "rust",
+        }
+    }
+
+    fn definitions(&self) -> &'static DefinitionPatterns {
+        match self {
+            Language::Python => &PYTHON_DEFINITIONS,
+            Language::JavaScript => &JAVASCRIPT_DEFINITIONS,
+            Language::Rust => &RUST_DEFINITIONS,
+        }
+    }
+
+    /// Whether a line belongs to the definition below it (decorator, attribute or comment).
+    fn is_definition_prefix(&self, line: &str) -> bool {
+        let trimmed = line.trim_start();
+        match self {
+            Language::Python => line.starts_with('@') || line.starts_with('#'),
+            // JSDoc continuation lines are indented by a single space
+            Language::JavaScript => {
+                line.len() - trimmed.len() <= 1
+                    && (trimmed.starts_with("//") || trimmed.starts_with("/*") || trimmed.starts_with('*'))
+            }
+            Language::Rust => line.starts_with("#[") || line.starts_with("//"),
+        }
+    }
+
+    /// Match a top-level definition, returning its kind and symbol name.
+    fn match_definition(&self, line: &str) -> Option<(&'static str, String)> {
+        // Only unindented lines start top-level definitions
+        if line.starts_with(char::is_whitespace) {
+            return None;
+        }
+
+        self.definitions().iter().find_map(|(kind, regex)| {
+            regex
+                .captures(line)
+                .and_then(|captures| captures.name("name"))
+                .map(|name| (*kind, name.as_str().trim().to_string()))
+        })
+    }
+}
+
+/// Chunker splitting source code on top-level function, class and impl boundaries.
+///
+/// Each chunk records its `language`, `symbol`, `symbol_kind` and the 1-based
+/// `start_line`/`end_line` range. Definitions longer than `max_chars` are split
+/// into consecutive line ranges of the same symbol.
+pub struct CodeChunker {
+    language: Language,
+    max_chars: usize,
+}
+
+impl CodeChunker {
+    /// Create a chunker for the given language.
+    pub fn new(language: Language, max_chars: usize) -> Self {
+        Self {
+            language,
+            max_chars: max_chars.max(1),
+        }
+    }
+
+    /// Create a chunker for a file if its extension is a supported language.
+    pub fn for_path(path: &str) -> Option<Self> {
+        Language::from_path(path).map(|language| Self::new(language, DEFAULT_MAX_CHARS_PER_CODE_CHUNK))
+    }
+
+    /// Emit one segment of lines, splitting it into windows if it is too long.
+    fn push_segment(&self, lines: &[&str], first_line: usize, symbol: Option<&(&'static str, String)>, chunks: &mut Vec<Chunk>) {
+        // Drop blank lines around the segment, keeping line numbers accurate
+        let start = match lines.iter().position(|line| !line.trim().is_empty()) {
+            Some(start) => start,
+            None => return,
+        };
+        let end = lines.iter().rposition(|line| !line.trim().is_empty()).unwrap_or(start) + 1;
+
+        let mut window_start = start;
+        let mut window_chars = 0;
+        for i in start..end {
+            let line_chars = lines[i].chars().count() + 1;
+            if i > window_start && window_chars + line_chars > self.max_chars {
+                chunks.push(self.code_chunk(&lines[window_start..i], first_line + window_start, symbol));
+                window_start = i;
+                window_chars = 0;
+            }
+            window_chars += line_chars;
+        }
+        chunks.push(self.code_chunk(&lines[window_start..end], first_line + window_start, symbol));
+    }
+
+    /// Build a code chunk with its location metadata.
+    fn code_chunk(&self, lines: &[&str], start_line: usize, symbol: Option<&(&'static str, String)>) -> Chunk {
+        let mut chunk = Chunk::new(lines.join("\n"));
+        chunk.metadata.insert("language".to_string(), Value::String(self.language.name().to_string()));
+        chunk.metadata.insert("start_line".to_string(), Value::Number(start_line.into()));
+        chunk.metadata.insert("end_line".to_string(), Value::Number((start_line + lines.len() - 1).into()));
+        if let Some((kind, name)) = symbol {
+            chunk.metadata.insert("symbol".to_string(), Value::String(name.clone()));
+            chunk.metadata.insert("symbol_kind".to_string(), Value::String(kind.to_string()));
+        }
+        chunk
+    }
+}
+
+impl Chunker for CodeChunker {
+    fn chunk(&self, text: &str) -> Vec<Chunk> {
+        let lines: Vec<&str> = text.lines().collect();
+
+        // Find where each top-level definition starts, including the decorators,
+        // attributes and comments directly above it
+        let mut boundaries: Vec<(usize, (&'static str, String))> = Vec::new();
+        for (i, line) in lines.iter().enumerate() {
+            if let Some(definition) = self.language.match_definition(line) {
+                let mut start = i;
+                let floor = boundaries.last().map(|(b, _)| *b + 1).unwrap_or(0);
+                while start > floor && self.language.is_definition_prefix(lines[start - 1]) {
+                    start -= 1;
+                }
+                boundaries.push((start, definition));
+            }
+        }
+
+        let mut chunks = Vec::new();
+
+        // Module-level code before the first definition (imports, constants, ...)
+        let first = boundaries.first().map(|(b, _)| *b).unwrap_or(lines.len());
+        self.push_segment(&lines[..first], 1, None, &mut chunks);
+
+        for (i, (start, symbol)) in boundaries.iter().enumerate() {
+            let end = boundaries.get(i + 1).map(|(b, _)| *b).unwrap_or(lines.len());
+            self.push_segment(&lines[*start..end], start + 1, Some(symbol), &mut chunks);
+        }
+
+        chunks
+    }
+
+    fn name(&self) -> &'static str {
+        "code"
+    }
+}
+
+/// Chunker keeping each part whole, for records such as CSV rows or JSON objects.
+pub struct RecordChunker;
+
+impl Chunker for RecordChunker {
+    fn chunk(&self, text: &str) -> Vec<Chunk> {
+        if text.trim().is_empty() {
+            return Vec::new();
+        }
+        vec![Chunk::new(text.trim().to_string())]
+    }
+
+    fn name(&self) -> &'static str {
+        "record"
+    }
+}
+
+/// Split text into sentences on common punctuation, ignoring empty lines.
+pub fn split_sentences(text: &str) -> Vec<String> {
     // Simple sentence splitting based on common punctuation
     let mut sentences = Vec::new();
     let mut current = String::new();
@@ -35,6 +672,13 @@ pub fn chunk_by_sentences(text: &str, max_sents: usize) -> Vec<String> {
         }
     }
 
+    sentences
+}
+
+/// Split text into chunks of sentences.
+pub fn chunk_by_sentences(text: &str, max_sents: usize) -> Vec<String> {
+    let sentences = split_sentences(text);
+
     // Group sentences into chunks
     let mut chunks = Vec::new();
     let mut current_chunk = Vec::new();
@@ -58,20 +702,43 @@ pub fn chunk_by_sentences(text: &str, max_sents: usize) -> Vec<String> {
 /// Process text into chunks with metadata.
 pub fn chunk_text(
     text: &str,
-    max_sents: usize,
+    chunker: &dyn Chunker,
     source_name: &str,
 ) -> Vec<HashMap<String, Value>> {
-    let chunks = chunk_by_sentences(text, max_sents);
-    
-    chunks
+    chunk_parts(&[DocumentPart::new(text.to_string())], chunker, source_name)
+}
+
+/// Process the parts of a document into chunks with metadata.
+///
+/// Each part is chunked on its own and its metadata (e.g. `page`) is copied into
+/// its chunks; chunk indices run across the whole document.
+pub fn chunk_parts(
+    parts: &[DocumentPart],
+    chunker: &dyn Chunker,
+    source_name: &str,
+) -> Vec<HashMap<String, Value>> {
+    let chunks: Vec<(Chunk, &HashMap<String, Value>)> = parts
         .iter()
+        .flat_map(|part| {
+            chunker
+                .chunk(&part.text)
+                .into_iter()
+                .map(move |chunk| (chunk, &part.metadata))
+        })
+        .collect();
+    let total_chunks = chunks.len();
+
+    chunks
+        .into_iter()
         .enumerate()
-        .map(|(i, chunk)| {
-            let mut metadata = HashMap::new();
-            metadata.insert("chunk_text".to_string(), Value::String(chunk.clone()));
+        .map(|(i, (chunk, part_metadata))| {
+            let mut metadata = part_metadata.clone();
+            metadata.extend(chunk.metadata);
+            metadata.insert("chunk_text".to_string(), Value::String(chunk.text));
             metadata.insert("source".to_string(), Value::String(source_name.to_string()));
             metadata.insert("chunk_index".to_string(), Value::Number(i.into()));
-            metadata.insert("total_chunks".to_string(), Value::Number(chunks.len().into()));
+            metadata.insert("total_chunks".to_string(), Value::Number(total_chunks.into()));
+            metadata.insert("chunker".to_string(), Value::String(chunker.name().to_string()));
             metadata
         })
         .collect()
@@ -86,13 +753,8 @@ pub fn list_text_files(directory: &str) -> Result<Vec<String>> {
         let entry = entry?;
         let path = entry.path();
         
-        if path.is_file() {
-            if let Some(extension) = path.extension() {
-                let ext = format!(".{}", extension.to_string_lossy().to_lowercase());
-                if TEXT_FILE_EXTENSIONS.contains(&ext.as_str()) {
-                    files.push(path.to_string_lossy().to_string());
-                }
-            }
+        if path.is_file() && has_text_extension(&path) {
+            files.push(path.to_string_lossy().to_string());
         }
     }
     
@@ -100,6 +762,73 @@ pub fn list_text_files(directory: &str) -> Result<Vec<String>> {
     Ok(files)
 }
 
+/// Check whether a path has one of the known text file extensions.
+fn has_text_extension(path: &Path) -> bool {
+    path.extension()
+        .map(|extension| {
+            let ext = format!(".{}", extension.to_string_lossy().to_lowercase());
+            TEXT_FILE_EXTENSIONS.contains(&ext.as_str())
+        })
+        .unwrap_or(false)
+}
+
+/// Compile a list of glob patterns into a single matcher.
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern).map_err(|e| anyhow!("Invalid glob pattern '{}': {}", pattern, e))?;
+        builder.add(glob);
+    }
+    Ok(builder.build()?)
+}
+
+/// Recursively list text files under a directory, honouring `.gitignore`.
+///
+/// Include and exclude globs are matched against paths relative to `directory`.
+/// When include patterns are given, a file must match at least one of them.
+pub fn walk_text_files(directory: &str, include: &[String], exclude: &[String]) -> Result<Vec<String>> {
+    let root = Path::new(directory);
+    if !root.is_dir() {
+        return Err(anyhow!("Not a directory: {}", directory));
+    }
+
+    let include_set = build_glob_set(include)?;
+    let exclude_set = build_glob_set(exclude)?;
+
+    let mut files = Vec::new();
+
+    // Respect .gitignore even when the directory is not inside a git repository
+    let walker = WalkBuilder::new(root).require_git(false).build();
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("Skipping unreadable entry: {}", e);
+                continue;
+            }
+        };
+
+        let path = entry.path();
+        if !path.is_file() || !has_text_extension(path) {
+            continue;
+        }
+
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        if !include.is_empty() && !include_set.is_match(relative) {
+            continue;
+        }
+        if exclude_set.is_match(relative) {
+            continue;
+        }
+
+        files.push(path.to_string_lossy().to_string());
+    }
+
+    files.sort();
+    info!("Found {} text files under {}", files.len(), directory);
+    Ok(files)
+}
+
 /// Read the content of a file.
 pub fn read_file_content(file_path: &str) -> Result<String> {
     let content = fs::read_to_string(file_path)?;
@@ -107,17 +836,331 @@ pub fn read_file_content(file_path: &str) -> Result<String> {
     Ok(content)
 }
 
+/// Last modification time of a file in seconds since the Unix epoch.
+pub fn file_mtime(file_path: &str) -> Option<u64> {
+    fs::metadata(file_path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+}
+
+/// Per-source metadata used to detect whether a file changed since it was embedded.
+pub fn source_metadata(file_path: &str, content: impl AsRef<[u8]>) -> HashMap<String, Value> {
+    let mut metadata = HashMap::new();
+    metadata.insert("source_hash".to_string(), Value::String(content_hash(content)));
+    if let Some(mtime) = file_mtime(file_path) {
+        metadata.insert("source_mtime".to_string(), Value::Number(mtime.into()));
+    }
+    metadata
+}
+
+/// Check whether a path names a Markdown file.
+pub fn is_markdown_file(file_path: &str) -> bool {
+    Path::new(file_path)
+        .extension()
+        .map(|extension| matches!(extension.to_string_lossy().to_lowercase().as_str(), "md" | "markdown"))
+        .unwrap_or(false)
+}
+
 /// Process a file into chunks with metadata.
+///
+/// The file is loaded by extension (e.g. PDFs page by page). Records of CSV and
+/// JSON files become one chunk each, and source code in a supported language is
+/// always split with a `CodeChunker`. Markdown files use `markdown_chunker` when
+/// given, and other files the given chunker. The path as given is used as the
+/// source name, so files with the same name in different directories stay distinct.
 pub fn process_file(
     file_path: &str,
-    max_sents: usize,
+    chunker: &dyn Chunker,
+    markdown_chunker: Option<&dyn Chunker>,
+    template: &RecordTemplate,
 ) -> Result<Vec<HashMap<String, Value>>> {
-    let content = read_file_content(file_path)?;
-    
-    let path = Path::new(file_path);
-    let source_name = path.file_name()
-        .map(|name| name.to_string_lossy().to_string())
-        .unwrap_or_else(|| file_path.to_string());
-    
-    Ok(chunk_text(&content, max_sents, &source_name))
-} 
\ No newline at end of file
+    let document = load_document(file_path, template)?;
+
+    let source_meta = source_metadata(file_path, &document.raw);
+    let mut chunks = if document.records {
+        chunk_parts(&document.parts, &RecordChunker, file_path)
+    } else {
+        match (CodeChunker::for_path(file_path), markdown_chunker) {
+            (Some(code_chunker), _) => chunk_parts(&document.parts, &code_chunker, file_path),
+            (None, Some(markdown_chunker)) if is_markdown_file(file_path) => {
+                chunk_parts(&document.parts, markdown_chunker, file_path)
+            }
+            _ => chunk_parts(&document.parts, chunker, file_path),
+        }
+    };
+    for chunk in chunks.iter_mut() {
+        chunk.extend(source_meta.clone());
+    }
+
+    Ok(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token_chunker(max_tokens: usize, overlap_tokens: usize) -> TokenChunker {
+        TokenChunker::new("text-embedding-3-small", max_tokens, overlap_tokens).unwrap()
+    }
+
+    #[test]
+    fn sentence_chunker_groups_sentences() {
+        let chunks = SentenceChunker::new(2).chunk("One. Two? Three! Four.\nFive");
+        let texts: Vec<&str> = chunks.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(texts, ["One. Two?", "Three! Four.", "Five"]);
+    }
+
+    #[test]
+    fn token_chunker_packs_sentences_within_budget() {
+        let chunker = token_chunker(12, 4);
+        let text = "The cat sat on the mat. The dog slept by the door. \
+            Birds sang in the old tree. Rain fell all night long.";
+        let chunks = chunker.chunk(text);
+
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            let tokens = chunker.count_tokens(&chunk.text);
+            assert!(tokens <= 12, "{:?} has {} tokens", chunk.text, tokens);
+            assert_eq!(chunk.metadata["token_count"], Value::from(tokens));
+        }
+        // Every sentence ends up in some chunk, in order
+        assert!(chunks[0].text.starts_with("The cat"));
+        assert!(chunks.last().unwrap().text.ends_with("all night long."));
+    }
+
+    #[test]
+    fn token_chunker_repeats_trailing_sentence_as_overlap() {
+        let sentences = ["Alpha beta gamma.", "Delta epsilon zeta.", "Eta theta iota."];
+        let longest = sentences.iter().map(|s| token_chunker(1000, 0).count_tokens(s)).max().unwrap();
+
+        // Room for two sentences per chunk, one of them repeated as overlap
+        let chunker = token_chunker(2 * longest, longest);
+        let chunks = chunker.chunk(&sentences.join(" "));
+        assert_eq!(chunks.len(), 2);
+        let last_sentence = split_sentences(&chunks[0].text).pop().unwrap();
+        assert!(chunks[1].text.starts_with(&last_sentence));
+    }
+
+    #[test]
+    fn long_sentence_is_split_into_windows() {
+        let chunker = token_chunker(10, 3);
+        let sentence = (0..60).map(|i| format!("word{}", i)).collect::<Vec<_>>().join(" ");
+        let chunks = chunker.chunk(&sentence);
+
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            assert!(chunker.count_tokens(&chunk.text) <= 10);
+        }
+        assert!(chunks[0].text.starts_with("word0"));
+        assert!(chunks.last().unwrap().text.ends_with("word59"));
+    }
+
+    #[test]
+    fn long_sentence_windows_never_cut_characters() {
+        let chunker = token_chunker(5, 2);
+        let sentence = "日本語の文章を分割するテスト🦀🦀🦀と絵文字😀😀😀を含む長い文".repeat(4);
+        let chunks = chunker.chunk(&sentence);
+
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            assert!(!chunk.text.contains('\u{FFFD}'), "{:?} was decoded lossily", chunk.text);
+            assert!(!chunk.text.is_empty());
+        }
+        // Without overlap the windows reassemble to the original sentence
+        let chunker = token_chunker(5, 0);
+        let joined: String = chunker.chunk(&sentence).into_iter().map(|c| c.text).collect();
+        assert_eq!(joined, sentence);
+    }
+
+    #[test]
+    fn markdown_chunks_record_heading_path() {
+        let text = "# Guide\n\nIntro text.\n\n## Setup\n\n### Docker\n\nRun the container.\n\n## Usage\n\nCall the API.";
+        let chunks = MarkdownChunker::new(40).chunk(text);
+
+        let sections: Vec<(&str, Option<&str>)> = chunks
+            .iter()
+            .map(|c| (c.text.as_str(), c.metadata.get("section").and_then(|s| s.as_str())))
+            .collect();
+        assert_eq!(
+            sections,
+            [
+                ("# Guide\n\nIntro text.", Some("Guide")),
+                ("### Docker\n\nRun the container.", Some("Guide > Setup > Docker")),
+                ("## Usage\n\nCall the API.", Some("Guide > Usage")),
+            ]
+        );
+    }
+
+    #[test]
+    fn markdown_keeps_small_fences_whole() {
+        let text = "## Build\n\n```sh\ncargo build\n\ncargo test\n```\n\nDone.";
+        let chunks = MarkdownChunker::new(200).chunk(text);
+
+        assert_eq!(chunks.len(), 1);
+        // The blank line inside the fence does not end the block
+        assert!(chunks[0].text.contains("```sh\ncargo build\n\ncargo test\n```"));
+    }
+
+    #[test]
+    fn markdown_splits_oversized_fences_between_lines() {
+        let body: Vec<String> = (0..30).map(|i| format!("let value_{} = {};", i, i)).collect();
+        let text = format!("# Code\n\n```rust\n{}\n```", body.join("\n"));
+        let chunks = MarkdownChunker::new(120).chunk(&text);
+
+        let code: Vec<&Chunk> = chunks.iter().filter(|c| c.text.contains("let value_")).collect();
+        assert!(code.len() > 1);
+        let mut lines = Vec::new();
+        for chunk in &code {
+            // The first piece also carries the section heading
+            let fenced = chunk.text.trim_start_matches("# Code\n\n");
+            assert!(fenced.chars().count() <= 120, "{:?} is too long", fenced);
+            let fenced = fenced.strip_prefix("```rust\n").unwrap();
+            assert!(fenced.ends_with("\n```"));
+            lines.extend(fenced.trim_end_matches("```").lines().map(str::to_string));
+        }
+        assert_eq!(lines, body);
+    }
+
+    /// Symbol, first line and last line of each code chunk.
+    fn code_spans(language: Language, text: &str) -> Vec<(Option<String>, u64, u64)> {
+        CodeChunker::new(language, 2000)
+            .chunk(text)
+            .iter()
+            .map(|chunk| {
+                (
+                    chunk.metadata.get("symbol").and_then(|s| s.as_str()).map(str::to_string),
+                    chunk.metadata["start_line"].as_u64().unwrap(),
+                    chunk.metadata["end_line"].as_u64().unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn python_decorators_stay_with_their_def() {
+        let text = "import functools\n\n@functools.cache\n@trace\ndef load(path):\n    return path\n\n\nclass Store:\n    pass\n";
+        let chunks = CodeChunker::new(Language::Python, 2000).chunk(text);
+
+        assert_eq!(
+            code_spans(Language::Python, text),
+            [(None, 1, 1), (Some("load".to_string()), 3, 6), (Some("Store".to_string()), 9, 10)]
+        );
+        assert!(chunks[1].text.starts_with("@functools.cache\n@trace\ndef load"));
+        assert_eq!(chunks[2].metadata["symbol_kind"], Value::from("class"));
+    }
+
+    #[test]
+    fn javascript_arrow_functions_and_classes_start_chunks() {
+        let text = "\
+/**
+ * Adds numbers.
+ */
+const add = (a, b) => {
+  return a + b;
+};
+export class Counter {
+  increment() {}
+}
+export const twice = async x => add(x, x);
+";
+        assert_eq!(
+            code_spans(Language::JavaScript, text),
+            [
+                (Some("add".to_string()), 1, 6),
+                (Some("Counter".to_string()), 7, 9),
+                (Some("twice".to_string()), 10, 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn rust_attributes_and_doc_comments_stay_with_their_item() {
+        let text = "\
+use std::fmt;
+
+/// A point.
+#[derive(Debug, Clone)]
+pub struct Point {
+    x: i32,
+}
+
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, \"{}\", self.x)
+    }
+}
+
+// Helper used by tests
+#[inline]
+pub(crate) fn origin() -> Point {
+    Point { x: 0 }
+}
+";
+        let chunks = CodeChunker::new(Language::Rust, 2000).chunk(text);
+        assert_eq!(
+            code_spans(Language::Rust, text),
+            [
+                (None, 1, 1),
+                (Some("Point".to_string()), 3, 7),
+                (Some("fmt::Display for Point".to_string()), 9, 13),
+                (Some("origin".to_string()), 15, 19),
+            ]
+        );
+        assert!(chunks[1].text.starts_with("/// A point.\n#[derive(Debug, Clone)]\npub struct Point"));
+        // Methods are indented, so they stay inside their impl
+        assert_eq!(chunks[2].metadata["symbol_kind"], Value::from("impl"));
+        assert_eq!(chunks[3].metadata["language"], Value::from("rust"));
+    }
+
+    #[test]
+    fn long_definitions_are_split_into_line_ranges() {
+        let body: String = (0..20).map(|i| format!("    x{} = {}\n", i, i)).collect();
+        let text = format!("def big():\n{}", body);
+        let chunks = CodeChunker::new(Language::Python, 60).chunk(&text);
+
+        assert!(chunks.len() > 1);
+        let mut next_line = 1;
+        for chunk in &chunks {
+            assert_eq!(chunk.metadata["symbol"], Value::from("big"));
+            let start = chunk.metadata["start_line"].as_u64().unwrap();
+            let end = chunk.metadata["end_line"].as_u64().unwrap();
+            assert_eq!(start, next_line);
+            assert_eq!(end - start + 1, chunk.text.lines().count() as u64);
+            next_line = end + 1;
+        }
+        assert_eq!(next_line, 22);
+    }
+
+    #[test]
+    fn markdown_files_are_picked_by_extension() {
+        let dir = std::env::temp_dir().join(format!("chunker-markdown-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let text = "# Guide\n\nFirst step. Second step.\n\n## Rollback\n\nAsk first.";
+        let markdown = dir.join("guide.md");
+        let plain = dir.join("guide.txt");
+        fs::write(&markdown, text).unwrap();
+        fs::write(&plain, text).unwrap();
+
+        let sentences = SentenceChunker::new(10);
+        let sections = MarkdownChunker::new(500);
+        let template = RecordTemplate::new(Vec::new(), None);
+        let chunker_names = |path: &Path, markdown_chunker: Option<&dyn Chunker>| -> Vec<String> {
+            process_file(path.to_str().unwrap(), &sentences, markdown_chunker, &template)
+                .unwrap()
+                .iter()
+                .map(|chunk| chunk["chunker"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        assert_eq!(chunker_names(&markdown, Some(&sections)), ["markdown", "markdown"]);
+        assert_eq!(chunker_names(&plain, Some(&sections)), ["sentence"]);
+        // Without a Markdown chunker the given chunker applies to every file
+        assert_eq!(chunker_names(&markdown, None), ["sentence"]);
+        assert!(is_markdown_file("notes/README.Markdown"));
+        assert!(!is_markdown_file("notes/mdfile.txt"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,405 @@
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::services::qdrant_service::PointId;
+
+/// Payload filter in Qdrant's filter format.
+///
+/// A point matches when all `must` conditions, at least one `should` condition
+/// (if any are given) and none of the `must_not` conditions hold.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Filter {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub must: Vec<Condition>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub should: Vec<Condition>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub must_not: Vec<Condition>,
+}
+
+impl Filter {
+    /// Create an empty filter matching every point.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Require a condition to hold.
+    pub fn must(mut self, condition: Condition) -> Self {
+        self.must.push(condition);
+        self
+    }
+
+    /// Require at least one of the `should` conditions to hold.
+    pub fn should(mut self, condition: Condition) -> Self {
+        self.should.push(condition);
+        self
+    }
+
+    /// Require a condition not to hold.
+    pub fn must_not(mut self, condition: Condition) -> Self {
+        self.must_not.push(condition);
+        self
+    }
+
+    /// Whether the filter has no conditions.
+    pub fn is_empty(&self) -> bool {
+        self.must.is_empty() && self.should.is_empty() && self.must_not.is_empty()
+    }
+}
+
+/// A single condition of a filter.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Condition {
+    Field(FieldCondition),
+    HasId { has_id: Vec<PointId> },
+    Filter(Filter),
+}
+
+/// Condition on the value of a payload field.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldCondition {
+    pub key: String,
+    #[serde(rename = "match", skip_serializing_if = "Option::is_none")]
+    pub matches: Option<Match>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range: Option<Range>,
+}
+
+/// Value match of a field condition.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Match {
+    /// The field equals the value, or contains it when the field is an array.
+    Value { value: Value },
+    /// The field equals any of the values.
+    Any { any: Vec<Value> },
+    /// The field equals none of the values.
+    Except { except: Vec<Value> },
+}
+
+/// Bounds of a range condition.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Range {
+    Numeric(Bounds<f64>),
+    /// RFC 3339 date-times or plain dates such as `2024-01-31`.
+    Datetime(Bounds<String>),
+}
+
+/// Lower and upper bounds, each optional.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Bounds<T> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gt: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gte: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lt: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lte: Option<T>,
+}
+
+impl Condition {
+    /// The field equals the value (or contains it, for array fields).
+    pub fn matches(key: &str, value: impl Into<Value>) -> Self {
+        Self::field(key, Some(Match::Value { value: value.into() }), None)
+    }
+
+    /// The field equals any of the values.
+    pub fn any<V: Into<Value>>(key: &str, values: impl IntoIterator<Item = V>) -> Self {
+        let any = values.into_iter().map(Into::into).collect();
+        Self::field(key, Some(Match::Any { any }), None)
+    }
+
+    /// The field equals none of the values.
+    pub fn except<V: Into<Value>>(key: &str, values: impl IntoIterator<Item = V>) -> Self {
+        let except = values.into_iter().map(Into::into).collect();
+        Self::field(key, Some(Match::Except { except }), None)
+    }
+
+    /// The chunk comes from the given source.
+    pub fn source(source: &str) -> Self {
+        Self::matches("source", source)
+    }
+
+    /// The chunk comes from one of the given sources.
+    pub fn sources<S: AsRef<str>>(sources: &[S]) -> Self {
+        Self::any("source", sources.iter().map(|s| s.as_ref().to_string()))
+    }
+
+    /// The `tags` field contains the tag.
+    pub fn tag(tag: &str) -> Self {
+        Self::matches("tags", tag)
+    }
+
+    /// The numeric field lies within the bounds.
+    pub fn range(key: &str, bounds: Bounds<f64>) -> Self {
+        Self::field(key, None, Some(Range::Numeric(bounds)))
+    }
+
+    /// The date-time field lies within the bounds.
+    pub fn date_range(key: &str, bounds: Bounds<String>) -> Self {
+        Self::field(key, None, Some(Range::Datetime(bounds)))
+    }
+
+    /// The point has one of the given ids.
+    pub fn has_id(ids: Vec<PointId>) -> Self {
+        Self::HasId { has_id: ids }
+    }
+
+    fn field(key: &str, matches: Option<Match>, range: Option<Range>) -> Self {
+        Self::Field(FieldCondition {
+            key: key.to_string(),
+            matches,
+            range,
+        })
+    }
+}
+
+static FILTER_TERM: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(?P<key>[A-Za-z_][\w.\[\]]*)(?P<op>!=|>=|<=|=|>|<)(?P<value>.+)$").unwrap());
+static DATE_VALUE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\d{4}-\d{2}-\d{2}([T ][\d:.]+)?(Z|[+-]\d{2}:?\d{2})?$").unwrap());
+
+/// Split text on a separator, except inside double quotes.
+fn split_unquoted(text: &str, is_separator: impl Fn(char) -> bool) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    for (i, c) in text.char_indices() {
+        if c == '"' {
+            quoted = !quoted;
+        } else if !quoted && is_separator(c) {
+            parts.push(&text[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    parts.push(&text[start..]);
+    parts.into_iter().filter(|part| !part.is_empty()).collect()
+}
+
+/// Split a filter expression into its terms; quoted values may contain spaces.
+pub fn split_filter_terms(expression: &str) -> Vec<&str> {
+    split_unquoted(expression, char::is_whitespace)
+}
+
+/// The text between double quotes, if the value is quoted.
+fn unquote(value: &str) -> Option<&str> {
+    value.strip_prefix('"')?.strip_suffix('"')
+}
+
+/// Interpret a filter value as the payload values it matches.
+///
+/// Quoted values are strings. Unquoted numbers and booleans match both the typed
+/// value and its text, since ids such as ticket numbers are often stored as strings.
+fn parse_filter_value(value: &str) -> Vec<Value> {
+    if let Some(text) = unquote(value) {
+        return vec![Value::String(text.to_string())];
+    }
+
+    let typed = if let Ok(number) = value.parse::<i64>() {
+        Some(Value::Number(number.into()))
+    } else if let Some(number) = value.parse::<f64>().ok().and_then(serde_json::Number::from_f64) {
+        Some(Value::Number(number))
+    } else {
+        match value {
+            "true" => Some(Value::Bool(true)),
+            "false" => Some(Value::Bool(false)),
+            _ => None,
+        }
+    };
+
+    let text = Value::String(value.to_string());
+    match typed {
+        Some(typed) => vec![typed, text],
+        None => vec![text],
+    }
+}
+
+/// Condition matching any of the values.
+///
+/// Qdrant matches values of one type per condition, so strings and typed values
+/// become separate conditions of which one must hold.
+fn match_values(key: &str, values: Vec<Value>) -> Condition {
+    let (strings, typed): (Vec<Value>, Vec<Value>) = values.into_iter().partition(Value::is_string);
+    let mut conditions: Vec<Condition> = [strings, typed]
+        .into_iter()
+        .filter(|values| !values.is_empty())
+        .map(|mut values| match values.len() {
+            1 => Condition::matches(key, values.remove(0)),
+            _ => Condition::any(key, values),
+        })
+        .collect();
+
+    match conditions.len() {
+        1 => conditions.remove(0),
+        _ => Condition::Filter(conditions.into_iter().fold(Filter::new(), Filter::should)),
+    }
+}
+
+/// Parse a whitespace-separated list of terms that must all hold.
+///
+/// Supported terms are `key=value`, `key=a,b` (any of), `key!=value`,
+/// `key!=a,b` and the comparisons `>`, `>=`, `<` and `<=` on numbers or dates,
+/// e.g. `source=handbook.md tags=ops source_mtime>=1700000000 date>=2024-01-01`.
+/// Values in double quotes are matched as strings and may contain spaces and
+/// commas, e.g. `ticket="12345" title="Release notes, 2024"`.
+impl FromStr for Filter {
+    type Err = anyhow::Error;
+
+    fn from_str(expression: &str) -> Result<Self> {
+        let mut filter = Filter::new();
+
+        for term in split_filter_terms(expression) {
+            let caps = FILTER_TERM
+                .captures(term)
+                .ok_or_else(|| anyhow!("Invalid filter term '{}', expected key=value", term))?;
+            let key = &caps["key"];
+            let value = &caps["value"];
+            let bound = unquote(value).unwrap_or(value);
+
+            filter = match &caps["op"] {
+                "=" | "!=" => {
+                    let values: Vec<Value> = split_unquoted(value, |c| c == ',')
+                        .into_iter()
+                        .flat_map(parse_filter_value)
+                        .collect();
+                    if values.is_empty() {
+                        return Err(anyhow!("Missing value in filter term '{}'", term));
+                    }
+                    let condition = match_values(key, values);
+                    if &caps["op"] == "=" {
+                        filter.must(condition)
+                    } else {
+                        filter.must_not(condition)
+                    }
+                }
+                op if DATE_VALUE.is_match(bound) => {
+                    let mut bounds = Bounds::default();
+                    let bound = Some(bound.to_string());
+                    match op {
+                        ">" => bounds.gt = bound,
+                        ">=" => bounds.gte = bound,
+                        "<" => bounds.lt = bound,
+                        _ => bounds.lte = bound,
+                    }
+                    filter.must(Condition::date_range(key, bounds))
+                }
+                op => {
+                    let number = bound
+                        .parse::<f64>()
+                        .map_err(|_| anyhow!("Expected a number or date in filter term '{}'", term))?;
+                    let mut bounds = Bounds::default();
+                    match op {
+                        ">" => bounds.gt = Some(number),
+                        ">=" => bounds.gte = Some(number),
+                        "<" => bounds.lt = Some(number),
+                        _ => bounds.lte = Some(number),
+                    }
+                    filter.must(Condition::range(key, bounds))
+                }
+            };
+        }
+
+        Ok(filter)
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", serde_json::to_string(self).map_err(|_| fmt::Error)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse(expression: &str) -> Value {
+        serde_json::to_value(expression.parse::<Filter>().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn strings_match_directly() {
+        assert_eq!(
+            parse("source=handbook.md tags=ops,dev"),
+            json!({"must": [
+                {"key": "source", "match": {"value": "handbook.md"}},
+                {"key": "tags", "match": {"any": ["ops", "dev"]}},
+            ]})
+        );
+    }
+
+    #[test]
+    fn unquoted_numbers_also_match_their_text() {
+        assert_eq!(
+            parse("ticket=12345"),
+            json!({"must": [{"should": [
+                {"key": "ticket", "match": {"value": "12345"}},
+                {"key": "ticket", "match": {"value": 12345}},
+            ]}]})
+        );
+        assert_eq!(
+            parse("active!=true"),
+            json!({"must_not": [{"should": [
+                {"key": "active", "match": {"value": "true"}},
+                {"key": "active", "match": {"value": true}},
+            ]}]})
+        );
+    }
+
+    #[test]
+    fn quoted_values_are_strings() {
+        assert_eq!(
+            parse(r#"ticket="12345" title="Release notes, 2024""#),
+            json!({"must": [
+                {"key": "ticket", "match": {"value": "12345"}},
+                {"key": "title", "match": {"value": "Release notes, 2024"}},
+            ]})
+        );
+        assert_eq!(
+            parse(r#"id="1",2"#),
+            json!({"must": [{"should": [
+                {"key": "id", "match": {"any": ["1", "2"]}},
+                {"key": "id", "match": {"value": 2}},
+            ]}]})
+        );
+    }
+
+    #[test]
+    fn comparisons_build_ranges() {
+        assert_eq!(
+            parse("price>=10 price<20.5 date>\"2024-01-01\""),
+            json!({"must": [
+                {"key": "price", "range": {"gte": 10.0}},
+                {"key": "price", "range": {"lt": 20.5}},
+                {"key": "date", "range": {"gt": "2024-01-01"}},
+            ]})
+        );
+    }
+
+    #[test]
+    fn invalid_terms_are_rejected() {
+        assert!("source".parse::<Filter>().is_err());
+        assert!("source=,".parse::<Filter>().is_err());
+        assert!("price>cheap".parse::<Filter>().is_err());
+        assert!("".parse::<Filter>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn display_is_qdrant_json() {
+        let filter = Filter::new()
+            .must(Condition::source("a.md"))
+            .must_not(Condition::has_id(vec![PointId::Num(7)]));
+        assert_eq!(
+            filter.to_string(),
+            r#"{"must":[{"key":"source","match":{"value":"a.md"}}],"must_not":[{"has_id":[7]}]}"#
+        );
+    }
+}
@@ -0,0 +1,550 @@
+use anyhow::{anyhow, Result};
+use log::info;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use scraper::{ElementRef, Html, Node, Selector};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::panic;
+use std::path::Path;
+
+/// A part of a document that is chunked on its own, such as a PDF page.
+///
+/// The metadata is copied into the payload of every chunk of the part.
+#[derive(Debug, Clone)]
+pub struct DocumentPart {
+    pub text: String,
+    pub metadata: HashMap<String, Value>,
+}
+
+impl DocumentPart {
+    /// Create a part without extra metadata.
+    pub fn new(text: String) -> Self {
+        Self {
+            text,
+            metadata: HashMap::new(),
+        }
+    }
+}
+
+/// A file loaded for chunking.
+pub struct LoadedDocument {
+    /// Raw file content, used to detect changes between runs.
+    pub raw: Vec<u8>,
+    pub parts: Vec<DocumentPart>,
+    /// Whether the parts are records (CSV rows, JSON objects) that are embedded whole.
+    pub records: bool,
+}
+
+/// Payload keys written by the indexer itself.
+///
+/// Record fields with one of these names are stored as `field_<name>` so they
+/// cannot overwrite chunk metadata or be mistaken for a point id.
+pub const RESERVED_PAYLOAD_KEYS: &[&str] = &[
+    "source", "source_hash", "source_mtime", "chunk_text", "chunk_index", "total_chunks",
+    "chunker", "model_name", "token_count", "page", "section", "symbol", "symbol_kind", "record_id",
+];
+
+/// Payload key under which a record field is stored.
+pub fn record_field_key(field: &str) -> String {
+    if RESERVED_PAYLOAD_KEYS.contains(&field) {
+        format!("field_{}", field)
+    } else {
+        field.to_string()
+    }
+}
+
+static TEMPLATE_FIELD: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{([^{}]+)\}").unwrap());
+
+/// How CSV rows and JSON records are turned into text.
+///
+/// The text is rendered from `template` (`{field}` placeholders) or, without a
+/// template, as `field: value` lines of the selected `text_fields`. Fields that
+/// are not part of the text are copied into the payload as metadata, renamed by
+/// `record_field_key` when they clash with reserved keys. When no fields are
+/// selected at all, every field is used for both.
+#[derive(Debug, Clone, Default)]
+pub struct RecordTemplate {
+    text_fields: Vec<String>,
+    template: Option<String>,
+}
+
+impl RecordTemplate {
+    /// Create a template from selected text fields and an optional text template.
+    pub fn new(text_fields: Vec<String>, template: Option<String>) -> Self {
+        let mut text_fields = text_fields;
+        if let Some(template) = &template {
+            for field in TEMPLATE_FIELD.captures_iter(template) {
+                let field = field[1].trim().to_string();
+                if !text_fields.contains(&field) {
+                    text_fields.push(field);
+                }
+            }
+        }
+        Self {
+            text_fields,
+            template,
+        }
+    }
+
+    /// Render a record into a document part, or `None` when it has no text.
+    pub fn render(&self, record: &Map<String, Value>) -> Option<DocumentPart> {
+        let text = match &self.template {
+            Some(template) => TEMPLATE_FIELD
+                .replace_all(template, |caps: &regex::Captures| {
+                    record.get(caps[1].trim()).map(record_value_text).unwrap_or_default()
+                })
+                .into_owned(),
+            None if self.text_fields.is_empty() => record
+                .iter()
+                .map(|(field, value)| (field, record_value_text(value)))
+                .filter(|(_, value)| !value.is_empty())
+                .map(|(field, value)| format!("{}: {}", field, value))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            None => self
+                .text_fields
+                .iter()
+                .filter_map(|field| record.get(field).map(|value| (field, record_value_text(value))))
+                .filter(|(_, value)| !value.is_empty())
+                .map(|(field, value)| format!("{}: {}", field, value))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        };
+        if text.trim().is_empty() {
+            return None;
+        }
+
+        let mut part = DocumentPart::new(text.trim().to_string());
+        for (field, value) in record {
+            if !value.is_null() && (self.text_fields.is_empty() || !self.text_fields.contains(field)) {
+                part.metadata.insert(record_field_key(field), value.clone());
+            }
+        }
+        Some(part)
+    }
+}
+
+/// Text of a record value; strings are used as-is, other values as compact JSON.
+fn record_value_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.trim().to_string(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// Interpret a CSV cell as a number or boolean where possible, so it can be filtered on.
+///
+/// Only cells that the typed value writes back unchanged are converted, so values
+/// such as `007`, `+1` or IDs too long for a float stay strings.
+fn csv_cell_value(cell: &str) -> Value {
+    let cell = cell.trim();
+    let typed = if let Ok(number) = cell.parse::<i64>() {
+        Value::Number(number.into())
+    } else if let Some(number) = cell.parse::<f64>().ok().and_then(serde_json::Number::from_f64) {
+        Value::Number(number)
+    } else {
+        match cell {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            "" => return Value::Null,
+            _ => return Value::String(cell.to_string()),
+        }
+    };
+
+    let written = typed.to_string();
+    if written == cell {
+        typed
+    } else {
+        Value::String(cell.to_string())
+    }
+}
+
+/// Turn each row of a CSV file with a header line into a record part.
+pub fn load_csv(raw: &[u8], template: &RecordTemplate) -> Result<Vec<DocumentPart>> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(raw);
+    let headers = reader
+        .headers()
+        .map_err(|e| anyhow!("Could not read CSV header: {}", e))?
+        .clone();
+
+    let mut parts = Vec::new();
+    for row in reader.records() {
+        let row = row.map_err(|e| anyhow!("Could not read CSV row: {}", e))?;
+        let record: Map<String, Value> = headers
+            .iter()
+            .zip(row.iter())
+            .map(|(field, cell)| (field.trim().to_string(), csv_cell_value(cell)))
+            .collect();
+        parts.extend(template.render(&record));
+    }
+    Ok(parts)
+}
+
+/// Turn a JSON array of objects, a single object, or JSON Lines into record parts.
+pub fn load_json(text: &str, template: &RecordTemplate, lines: bool) -> Result<Vec<DocumentPart>> {
+    let values: Vec<Value> = if lines {
+        text.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                serde_json::from_str(line).map_err(|e| anyhow!("Invalid JSON on line {}: {}", i + 1, e))
+            })
+            .collect::<Result<_>>()?
+    } else {
+        match serde_json::from_str(text).map_err(|e| anyhow!("Invalid JSON: {}", e))? {
+            Value::Array(values) => values,
+            value => vec![value],
+        }
+    };
+
+    Ok(values
+        .into_iter()
+        .filter_map(|value| match value {
+            Value::Object(record) => template.render(&record),
+            other => template.render(&Map::from_iter([("value".to_string(), other)])),
+        })
+        .collect())
+}
+
+/// Check whether a file has the given extension, ignoring case.
+fn has_extension(file_path: &str, extension: &str) -> bool {
+    Path::new(file_path)
+        .extension()
+        .map(|ext| ext.to_string_lossy().eq_ignore_ascii_case(extension))
+        .unwrap_or(false)
+}
+
+/// Extract the text of each page of a PDF, recording 1-based `page` numbers.
+pub fn load_pdf(raw: &[u8]) -> Result<Vec<DocumentPart>> {
+    // The PDF parser panics on some malformed documents; treat that as a load error
+    let pages = panic::catch_unwind(|| pdf_extract::extract_text_from_mem_by_pages(raw))
+        .map_err(|_| anyhow!("PDF parser crashed on malformed document"))?
+        .map_err(|e| anyhow!("Could not extract PDF text: {}", e))?;
+
+    Ok(page_parts(pages))
+}
+
+/// Turn the text of consecutive pages into parts numbered from 1, skipping blank pages.
+fn page_parts(pages: Vec<String>) -> Vec<DocumentPart> {
+    pages
+        .into_iter()
+        .enumerate()
+        .filter(|(_, text)| !text.trim().is_empty())
+        .map(|(i, text)| {
+            let mut part = DocumentPart::new(text);
+            part.metadata.insert("page".to_string(), Value::Number((i + 1).into()));
+            part
+        })
+        .collect()
+}
+
+/// Elements whose content is never part of the readable text.
+const SKIPPED_HTML_ELEMENTS: &[&str] = &[
+    "head", "script", "style", "nav", "noscript", "template", "svg", "iframe",
+];
+
+/// Elements that start a new block of text.
+const BLOCK_HTML_ELEMENTS: &[&str] = &[
+    "p", "div", "section", "article", "main", "header", "footer", "aside", "blockquote",
+    "ul", "ol", "dl", "dt", "dd", "table", "tr", "figure", "figcaption", "form", "hr",
+];
+
+static TITLE_SELECTOR: Lazy<Selector> = Lazy::new(|| Selector::parse("title").unwrap());
+static CANONICAL_SELECTOR: Lazy<Selector> =
+    Lazy::new(|| Selector::parse(r#"link[rel~="canonical"][href]"#).unwrap());
+static BODY_SELECTOR: Lazy<Selector> = Lazy::new(|| Selector::parse("body").unwrap());
+
+/// Convert an HTML page into clean text, recording its `title` and `canonical_url`.
+///
+/// Scripts, styles and navigation are dropped. Headings become Markdown headings
+/// and links keep their target as `[text](href)`, so the result also suits the
+/// Markdown chunker.
+pub fn load_html(html: &str) -> DocumentPart {
+    let document = Html::parse_document(html);
+
+    let mut text = String::new();
+    match document.select(&BODY_SELECTOR).next() {
+        Some(body) => write_html_text(body, &mut text, false),
+        None => write_html_text(document.root_element(), &mut text, false),
+    }
+
+    let mut part = DocumentPart::new(tidy_text(&text));
+    if let Some(title) = document.select(&TITLE_SELECTOR).next() {
+        let title = collapse_whitespace(&title.text().collect::<String>());
+        if !title.is_empty() {
+            part.metadata.insert("title".to_string(), Value::String(title));
+        }
+    }
+    if let Some(href) = document
+        .select(&CANONICAL_SELECTOR)
+        .next()
+        .and_then(|link| link.value().attr("href"))
+    {
+        part.metadata.insert("canonical_url".to_string(), Value::String(href.trim().to_string()));
+    }
+    part
+}
+
+/// Append the readable text of an element and its descendants.
+fn write_html_text(element: ElementRef, out: &mut String, preformatted: bool) {
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => {
+                if preformatted {
+                    out.push_str(text);
+                } else {
+                    // Whitespace between inline elements still separates words
+                    let collapsed = collapse_whitespace(text);
+                    if text.starts_with(char::is_whitespace) {
+                        push_space(out);
+                    }
+                    out.push_str(&collapsed);
+                    if !collapsed.is_empty() && text.ends_with(char::is_whitespace) {
+                        push_space(out);
+                    }
+                }
+            }
+            Node::Element(_) => {
+                let Some(child) = ElementRef::wrap(child) else { continue };
+                write_html_element(child, out, preformatted);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Append the readable text of a single element.
+fn write_html_element(element: ElementRef, out: &mut String, preformatted: bool) {
+    let name = element.value().name();
+    if SKIPPED_HTML_ELEMENTS.contains(&name) {
+        return;
+    }
+
+    match name {
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            let level = name[1..].parse::<usize>().unwrap_or(1);
+            let heading = collapse_whitespace(&element.text().collect::<String>());
+            if !heading.is_empty() {
+                out.push_str(&format!("\n\n{} {}\n\n", "#".repeat(level), heading));
+            }
+        }
+        "a" => {
+            let mut label = String::new();
+            write_html_text(element, &mut label, preformatted);
+            let label = label.trim();
+            match element.value().attr("href").map(str::trim) {
+                Some(href) if !href.is_empty() && !href.starts_with('#') && !label.is_empty() => {
+                    out.push_str(&format!("[{}]({})", label, href));
+                }
+                _ => out.push_str(label),
+            }
+        }
+        "br" => out.push('\n'),
+        "li" => {
+            out.push_str("\n- ");
+            write_html_text(element, out, preformatted);
+            out.push('\n');
+        }
+        "td" | "th" => {
+            write_html_text(element, out, preformatted);
+            out.push_str(" | ");
+        }
+        "pre" => {
+            out.push_str("\n\n");
+            write_html_text(element, out, true);
+            out.push_str("\n\n");
+        }
+        _ if BLOCK_HTML_ELEMENTS.contains(&name) => {
+            out.push_str("\n\n");
+            write_html_text(element, out, preformatted);
+            out.push_str("\n\n");
+        }
+        _ => write_html_text(element, out, preformatted),
+    }
+}
+
+/// Push a single space unless the text already ends with whitespace.
+fn push_space(out: &mut String) {
+    if !out.is_empty() && !out.ends_with(char::is_whitespace) {
+        out.push(' ');
+    }
+}
+
+/// Collapse runs of whitespace into single spaces.
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Trim trailing spaces from lines and keep at most one blank line between blocks.
+fn tidy_text(text: &str) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    for line in text.lines().map(str::trim_end) {
+        if line.trim().is_empty() && lines.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(if line.trim().is_empty() { "" } else { line });
+    }
+    while lines.last().is_some_and(|last| last.is_empty()) {
+        lines.pop();
+    }
+    lines.join("\n")
+}
+
+/// Load a file into parts, picking a loader by extension.
+///
+/// PDFs yield one part per page, HTML pages are converted to clean text, and
+/// CSV, JSON and JSON Lines files yield one record per row or object rendered
+/// with `template`; other files are read as a single UTF-8 text part.
+pub fn load_document(file_path: &str, template: &RecordTemplate) -> Result<LoadedDocument> {
+    let raw = fs::read(file_path)?;
+
+    let mut records = false;
+    let parts = if has_extension(file_path, "pdf") {
+        load_pdf(&raw)?
+    } else if has_extension(file_path, "csv") {
+        records = true;
+        load_csv(&raw, template)?
+    } else {
+        let text = String::from_utf8(raw.clone())
+            .map_err(|_| anyhow!("{} is not valid UTF-8 text", file_path))?;
+        if has_extension(file_path, "html") || has_extension(file_path, "htm") {
+            vec![load_html(&text)]
+        } else if has_extension(file_path, "json") {
+            records = true;
+            load_json(&text, template, false)?
+        } else if has_extension(file_path, "jsonl") || has_extension(file_path, "ndjson") {
+            records = true;
+            load_json(&text, template, true)?
+        } else {
+            vec![DocumentPart::new(text)]
+        }
+    };
+
+    let characters: usize = parts.iter().map(|part| part.text.len()).sum();
+    info!("Loaded {} characters in {} parts from {}", characters, parts.len(), file_path);
+
+    Ok(LoadedDocument { raw, parts, records })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::chunker::{chunk_parts, SentenceChunker};
+
+    #[test]
+    fn record_fields_keep_their_names_and_types() {
+        let template = RecordTemplate::new(vec!["name".to_string()], None);
+        let parts = load_csv(b"name,price,active\nWidget,12,true\n", &template).unwrap();
+
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].text, "name: Widget");
+        assert_eq!(parts[0].metadata["price"], Value::from(12));
+        assert_eq!(parts[0].metadata["active"], Value::Bool(true));
+        assert!(!parts[0].metadata.contains_key("name"));
+    }
+
+    #[test]
+    fn reserved_record_fields_are_renamed() {
+        let template = RecordTemplate::new(Vec::new(), Some("{title}".to_string()));
+        let parts = load_csv(b"title,record_id,source,page\nHello,7,crm,3\n", &template).unwrap();
+        let metadata = &parts[0].metadata;
+
+        assert_eq!(metadata["field_record_id"], Value::from(7));
+        assert_eq!(metadata["field_source"], Value::from("crm"));
+        assert_eq!(metadata["field_page"], Value::from(3));
+        for key in RESERVED_PAYLOAD_KEYS {
+            assert!(!metadata.contains_key(*key), "{} leaked into the payload", key);
+        }
+    }
+
+    #[test]
+    fn csv_cells_keep_leading_zeros_and_long_ids() {
+        let template = RecordTemplate::new(Vec::new(), Some("{id} in {zip}".to_string()));
+        let csv = b"id,zip,code,count,ratio,sci\n12345678901234567890,02134,+1,42,0.5,1e3\n";
+        let parts = load_csv(csv, &template).unwrap();
+        let metadata = &parts[0].metadata;
+
+        assert_eq!(parts[0].text, "12345678901234567890 in 02134");
+        assert_eq!(metadata["code"], Value::from("+1"));
+        assert_eq!(metadata["count"], Value::from(42));
+        assert_eq!(metadata["ratio"], Value::from(0.5));
+        assert_eq!(metadata["sci"], Value::from("1e3"));
+        assert_eq!(csv_cell_value("007"), Value::from("007"));
+        assert_eq!(csv_cell_value("12345678901234567890"), Value::from("12345678901234567890"));
+        assert_eq!(csv_cell_value("-3"), Value::from(-3));
+        assert_eq!(csv_cell_value("TRUE"), Value::from("TRUE"));
+    }
+
+    #[test]
+    fn html_drops_scripts_styles_and_navigation() {
+        let html = r#"<html><head><style>body { color: red }</style></head><body>
+            <nav><a href="/">Home</a> | <a href="/docs">Docs</a></nav>
+            <script>trackVisit("secret");</script>
+            <p>Deploys run <b>every</b> Tuesday.</p>
+            <noscript>Enable JavaScript</noscript>
+        </body></html>"#;
+        let part = load_html(html);
+
+        assert_eq!(part.text, "Deploys run every Tuesday.");
+    }
+
+    #[test]
+    fn html_headings_become_markdown_headings() {
+        let html = "<body><h1>Handbook</h1><p>Intro.</p><h3>  On\n call </h3><p>Page the lead.</p>\
+            <h6>Footnote</h6></body>";
+        let part = load_html(html);
+
+        assert_eq!(
+            part.text,
+            "# Handbook\n\nIntro.\n\n### On call\n\nPage the lead.\n\n###### Footnote"
+        );
+    }
+
+    #[test]
+    fn html_title_and_canonical_url_are_recorded() {
+        let html = r#"<html><head>
+            <title> Deploy
+              guide </title>
+            <link rel="alternate" href="https://example.com/feed">
+            <link rel="canonical" href=" https://example.com/deploy ">
+        </head><body><p>See the <a href="https://example.com/runbook">runbook</a>.</p></body></html>"#;
+        let part = load_html(html);
+
+        assert_eq!(part.metadata["title"], Value::from("Deploy guide"));
+        assert_eq!(part.metadata["canonical_url"], Value::from("https://example.com/deploy"));
+        assert_eq!(part.text, "See the [runbook](https://example.com/runbook).");
+
+        let part = load_html("<p>No head.</p>");
+        assert!(!part.metadata.contains_key("title"));
+        assert!(!part.metadata.contains_key("canonical_url"));
+    }
+
+    #[test]
+    fn pdf_pages_keep_their_numbers_through_chunking() {
+        let pages = vec![
+            "Cover page.".to_string(),
+            " \n".to_string(),
+            "Deploys run on Tuesday. Rollbacks need approval.".to_string(),
+        ];
+        let parts = page_parts(pages);
+        let numbers: Vec<&Value> = parts.iter().map(|part| &part.metadata["page"]).collect();
+        assert_eq!(numbers, [&Value::from(1), &Value::from(3)]);
+
+        let chunks = chunk_parts(&parts, &SentenceChunker::new(1), "manual.pdf");
+        let pages: Vec<(&str, &Value)> = chunks
+            .iter()
+            .map(|chunk| (chunk["chunk_text"].as_str().unwrap(), &chunk["page"]))
+            .collect();
+        assert_eq!(
+            pages,
+            [
+                ("Cover page.", &Value::from(1)),
+                ("Deploys run on Tuesday.", &Value::from(3)),
+                ("Rollbacks need approval.", &Value::from(3)),
+            ]
+        );
+    }
+}
@@ -1,2 +1,5 @@
 pub mod qdrant_service;
-pub mod chunker; 
\ No newline at end of file
+pub mod chunker;
+pub mod loader;
+pub mod filter;
+pub mod sparse; 
\ No newline at end of file
@@ -2,10 +2,16 @@ use anyhow::{anyhow, Result};
 use log::{debug, info};
 use reqwest::Client as HttpClient;
 use serde::{Deserialize, Serialize};
-use serde_json::{Map, Value};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+use uuid::Uuid;
 
-use crate::config::{QDRANT_API_KEY, QDRANT_COLLECTION, QDRANT_URL};
+use crate::config::{QDRANT_API_KEY, QDRANT_COLLECTION, QDRANT_URL, RRF_K, SPARSE_VECTOR_NAME};
+use crate::services::filter::{Bounds, Condition, Filter};
+use crate::services::sparse::SparseVector;
 
 // Qdrant API types
 #[derive(Debug, Serialize)]
@@ -14,22 +20,82 @@ struct VectorParams {
     distance: String,
 }
 
+#[derive(Debug, Serialize)]
+struct SparseVectorParams {
+    modifier: String,
+}
+
 #[derive(Debug, Serialize)]
 struct CreateCollectionRequest {
     vectors: VectorParams,
+    sparse_vectors: HashMap<String, SparseVectorParams>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// Identifier of a point in a Qdrant collection.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 #[serde(untagged)]
-enum PointId {
+pub enum PointId {
     Num(u64),
     Uuid(String),
 }
 
+impl PointId {
+    /// Derive a deterministic id for a chunk from its source, position and content.
+    ///
+    /// The same chunk of the same source always maps to the same id, so re-embedding
+    /// a file updates its points in place while different files never collide.
+    pub fn for_chunk(source: &str, chunk_index: usize, chunk_text: &str) -> Self {
+        let content_hash = content_hash(chunk_text);
+        let name = format!("{}:{}:{}", source, chunk_index, content_hash);
+        PointId::Uuid(Uuid::new_v5(&Uuid::NAMESPACE_OID, name.as_bytes()).to_string())
+    }
+
+    /// Derive a deterministic id for a record with a caller-provided id.
+    ///
+    /// Sending a record with the same id for the same source again updates its point.
+    /// The id is hashed as JSON, so `1` and `"1"` are different records.
+    pub fn for_record(source: &str, record_id: &Value) -> Self {
+        let name = format!("{}#{}", source, record_id);
+        PointId::Uuid(Uuid::new_v5(&Uuid::NAMESPACE_OID, name.as_bytes()).to_string())
+    }
+}
+
+impl fmt::Display for PointId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PointId::Num(id) => write!(f, "{}", id),
+            PointId::Uuid(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+/// Hex-encoded SHA-256 digest of a piece of text or raw file content.
+pub fn content_hash(content: impl AsRef<[u8]>) -> String {
+    Sha256::digest(content.as_ref())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Vectors of a point: the dense vector alone, or alongside named sparse vectors.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum PointVectors {
+    Dense(Vec<f32>),
+    Named(HashMap<String, VectorData>),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum VectorData {
+    Dense(Vec<f32>),
+    Sparse(SparseVector),
+}
+
 #[derive(Debug, Serialize)]
 struct Point {
-    id: u64,
-    vector: Vec<f32>,
+    id: PointId,
+    vector: PointVectors,
     payload: Map<String, Value>,
 }
 
@@ -44,6 +110,25 @@ struct SearchRequest {
     limit: u64,
     with_payload: bool,
     score_threshold: f32,
+    with_vector: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    filter: Option<Filter>,
+}
+
+#[derive(Debug, Serialize)]
+struct NamedSparseVector {
+    name: String,
+    vector: SparseVector,
+}
+
+#[derive(Debug, Serialize)]
+struct SparseSearchRequest {
+    vector: NamedSparseVector,
+    limit: u64,
+    with_payload: bool,
+    with_vector: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    filter: Option<Filter>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -62,9 +147,30 @@ struct ListCollectionsResponse {
 
 #[derive(Debug, Deserialize)]
 struct SearchResponseHit {
-    id: u64,
+    id: PointId,
     score: f32,
     payload: Map<String, Value>,
+    vector: Option<Value>,
+}
+
+impl From<SearchResponseHit> for SearchHit {
+    fn from(hit: SearchResponseHit) -> Self {
+        // Collections with sparse vectors return named vectors, the dense one under ""
+        let vector = match hit.vector {
+            Some(Value::Object(mut named)) => named.remove(""),
+            other => other,
+        }
+        .and_then(|vector| serde_json::from_value(vector).ok());
+
+        Self {
+            id: hit.id,
+            score: hit.score,
+            // Convert Map<String, Value> to HashMap<String, Value>
+            payload: hit.payload.into_iter().collect(),
+            vector,
+            rerank_score: None,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -72,12 +178,134 @@ struct SearchResponse {
     result: Vec<SearchResponseHit>,
 }
 
+#[derive(Debug, Serialize)]
+struct ScrollRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    filter: Option<Filter>,
+    limit: u64,
+    with_payload: Value,
+    with_vector: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    offset: Option<PointId>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScrollPoint {
+    #[allow(dead_code)]
+    id: PointId,
+    payload: Option<Map<String, Value>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScrollResult {
+    points: Vec<ScrollPoint>,
+    next_page_offset: Option<PointId>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScrollResponse {
+    result: ScrollResult,
+}
+
+#[derive(Debug, Serialize)]
+struct DeleteRequest {
+    filter: Filter,
+}
+
+#[derive(Debug, Serialize)]
+struct SetPayloadRequest {
+    payload: Value,
+    filter: Filter,
+}
+
+/// A point returned by a search, with its score and payload.
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub id: PointId,
+    /// Cosine similarity to the query for dense and hybrid search, the BM25
+    /// score for keyword search.
+    pub score: f32,
+    pub payload: HashMap<String, Value>,
+    /// Dense vector of the point, when requested with `with_vector`.
+    pub vector: Option<Vec<f32>>,
+    /// Score given by a reranker, on that reranker's own scale.
+    pub rerank_score: Option<f32>,
+}
+
+impl SearchHit {
+    /// Score the hit is ranked by: the rerank score if it was reranked, else `score`.
+    pub fn ranking_score(&self) -> f32 {
+        self.rerank_score.unwrap_or(self.score)
+    }
+}
+
+/// Cosine similarity of two vectors.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+/// Merge ranked result lists, scoring each point by the sum of `1 / (k + rank)`.
+pub fn reciprocal_rank_fusion(
+    rankings: Vec<Vec<SearchHit>>,
+    k: f32,
+) -> Vec<SearchHit> {
+    let mut fused: Vec<SearchHit> = Vec::new();
+    let mut positions: HashMap<PointId, usize> = HashMap::new();
+
+    for ranking in rankings {
+        for (rank, mut hit) in ranking.into_iter().enumerate() {
+            let score = 1.0 / (k + rank as f32 + 1.0);
+            match positions.get(&hit.id) {
+                Some(&position) => fused[position].score += score,
+                None => {
+                    positions.insert(hit.id.clone(), fused.len());
+                    hit.score = score;
+                    fused.push(hit);
+                }
+            }
+        }
+    }
+
+    fused.sort_by(|a, b| b.score.total_cmp(&a.score));
+    fused
+}
+
+/// Indexing state of a source as recorded in the payload of its points.
+#[derive(Debug, Clone, Default)]
+pub struct SourceState {
+    pub content_hash: Option<String>,
+    pub mtime: Option<u64>,
+    pub points: usize,
+}
+
+impl SourceState {
+    // Count a point of the source and take the hash and time from its payload
+    fn add_point(&mut self, payload: &Map<String, Value>) {
+        self.points += 1;
+        if let Some(hash) = payload.get("source_hash").and_then(|h| h.as_str()) {
+            self.content_hash = Some(hash.to_string());
+        }
+        if let Some(mtime) = payload.get("source_mtime").and_then(|m| m.as_u64()) {
+            self.mtime = Some(mtime);
+        }
+    }
+}
+
 /// Service for interacting with Qdrant vector database.
 pub struct QdrantService {
     client: HttpClient,
     base_url: String,
     collection_name: String,
     api_key: Option<String>,
+    /// Whether the collection stores BM25 sparse vectors for hybrid search.
+    sparse_vectors: bool,
 }
 
 impl QdrantService {
@@ -86,17 +314,7 @@ impl QdrantService {
         collection_name: Option<String>,
         vector_size: Option<usize>,
     ) -> Result<Self> {
-        let base_url = QDRANT_URL.clone();
-        let api_key = QDRANT_API_KEY.clone();
-        let client = HttpClient::new();
-        let collection_name = collection_name.unwrap_or_else(|| QDRANT_COLLECTION.clone());
-
-        let service = Self {
-            client,
-            base_url,
-            collection_name,
-            api_key,
-        };
+        let mut service = Self::connect(collection_name.unwrap_or_else(|| QDRANT_COLLECTION.clone()));
 
         // Check if collection exists, create if needed
         let collections = service.list_collections().await?;
@@ -109,6 +327,7 @@ impl QdrantService {
                 info!("Creating collection '{}' with vector size {}", service.collection_name, vector_size);
                 
                 service.create_collection(vector_size).await?;
+                service.sparse_vectors = true;
             } else {
                 return Err(anyhow!(
                     "Collection '{}' does not exist. Provide vector_size to create it.",
@@ -117,11 +336,40 @@ impl QdrantService {
             }
         } else {
             info!("Using existing collection: {}", service.collection_name);
+            service.sparse_vectors = service.has_sparse_vectors().await?;
+            if !service.sparse_vectors {
+                debug!(
+                    "Collection '{}' has no '{}' sparse vectors; keyword search is disabled until it is re-created",
+                    service.collection_name, SPARSE_VECTOR_NAME
+                );
+            }
         }
 
         Ok(service)
     }
 
+    /// Open a collection only if it already exists.
+    ///
+    /// Returns `None` for a missing collection; connection and authentication
+    /// errors are returned as errors.
+    pub async fn open_existing(collection_name: &str) -> Result<Option<Self>> {
+        if !Self::connect(collection_name.to_string()).check_collection_exists().await? {
+            return Ok(None);
+        }
+        Self::new(Some(collection_name.to_string()), None).await.map(Some)
+    }
+
+    // Client for a collection, without contacting the server
+    fn connect(collection_name: String) -> Self {
+        Self {
+            client: HttpClient::new(),
+            base_url: QDRANT_URL.clone(),
+            collection_name,
+            api_key: QDRANT_API_KEY.clone(),
+            sparse_vectors: false,
+        }
+    }
+
     // Helper to build a request with auth headers
     fn request_builder(&self, url: &str) -> reqwest::RequestBuilder {
         let builder = self.client.get(url);
@@ -134,7 +382,7 @@ impl QdrantService {
     }
 
     /// List all collections
-    async fn list_collections(&self) -> Result<Vec<String>> {
+    pub async fn list_collections(&self) -> Result<Vec<String>> {
         let url = format!("{}/collections", self.base_url);
         let response = self.request_builder(&url)
             .send()
@@ -172,20 +420,70 @@ impl QdrantService {
                 Ok(Vec::new())
             },
             Err(e) => {
-                return Err(anyhow!("Failed to parse Qdrant response: {} - Response was: {}", e, response_text));
+                Err(anyhow!("Failed to parse Qdrant response: {} - Response was: {}", e, response_text))
             }
         }
     }
 
+    // Fetch the collection's info, including its configuration
+    async fn collection_info(&self) -> Result<Value> {
+        let url = format!("{}/collections/{}", self.base_url, self.collection_name);
+        let response = self.request_builder(&url)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            return Err(anyhow!("API error: {}", error_text));
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// Check whether the collection was created with the BM25 sparse vectors.
+    async fn has_sparse_vectors(&self) -> Result<bool> {
+        let info = self.collection_info().await?;
+        Ok(info
+            .pointer("/result/config/params/sparse_vectors")
+            .and_then(|sparse| sparse.get(SPARSE_VECTOR_NAME))
+            .is_some())
+    }
+
+    /// Size of the collection's dense vectors, or `None` if it only has named vectors.
+    pub async fn vector_size(&self) -> Result<Option<usize>> {
+        let info = self.collection_info().await?;
+        Ok(info
+            .pointer("/result/config/params/vectors/size")
+            .and_then(|size| size.as_u64())
+            .map(|size| size as usize))
+    }
+
+    /// Name of the collection this service works on.
+    pub fn collection_name(&self) -> &str {
+        &self.collection_name
+    }
+
+    /// Whether the collection supports keyword (sparse vector) search.
+    pub fn supports_sparse(&self) -> bool {
+        self.sparse_vectors
+    }
+
     /// Create a new collection
     async fn create_collection(&self, vector_size: usize) -> Result<()> {
         let url = format!("{}/collections/{}", self.base_url, self.collection_name);
         
+        // Qdrant applies the inverse document frequency to the stored term weights
         let request = CreateCollectionRequest {
             vectors: VectorParams {
                 size: vector_size,
                 distance: "Cosine".to_string(),
             },
+            sparse_vectors: HashMap::from([(
+                SPARSE_VECTOR_NAME.to_string(),
+                SparseVectorParams {
+                    modifier: "idf".to_string(),
+                },
+            )]),
         };
 
         let response = self.client
@@ -204,20 +502,37 @@ impl QdrantService {
     }
 
     /// Insert or update vectors in the collection.
+    ///
+    /// Sparse vectors are stored alongside the dense ones when the collection
+    /// supports them and ignored otherwise; pass an empty list to store none.
     pub async fn upsert(
         &self,
-        ids: Vec<u64>,
+        ids: Vec<PointId>,
         vectors: Vec<Vec<f32>>,
+        sparse_vectors: Vec<SparseVector>,
         payloads: Vec<HashMap<String, Value>>,
     ) -> Result<()> {
         if ids.len() != vectors.len() || ids.len() != payloads.len() {
             return Err(anyhow!("Ids, vectors, and payloads must have the same length"));
         }
+        if !sparse_vectors.is_empty() && sparse_vectors.len() != ids.len() {
+            return Err(anyhow!("Sparse vectors must match the number of points"));
+        }
 
+        let mut sparse_vectors = sparse_vectors.into_iter();
         let mut points = Vec::new();
         for ((id, vector), payload) in ids.into_iter().zip(vectors).zip(payloads) {
             // Convert HashMap<String, Value> to Map<String, Value>
             let payload_map: Map<String, Value> = payload.into_iter().collect();
+
+            let vector = match sparse_vectors.next() {
+                Some(sparse) if self.sparse_vectors => PointVectors::Named(HashMap::from([
+                    // The unnamed dense vector is addressed by the empty name
+                    (String::new(), VectorData::Dense(vector)),
+                    (SPARSE_VECTOR_NAME.to_string(), VectorData::Sparse(sparse)),
+                ])),
+                _ => PointVectors::Dense(vector),
+            };
             
             points.push(Point {
                 id,
@@ -251,13 +566,17 @@ impl QdrantService {
         Ok(())
     }
 
-    /// Search for similar vectors in the collection.
+    /// Search for similar vectors in the collection, optionally restricted by a payload filter.
+    ///
+    /// With `with_vector`, the dense vector of each hit is returned as well.
     pub async fn search(
         &self,
         vector: Vec<f32>,
         top_k: u64,
         score_threshold: f32,
-    ) -> Result<Vec<(u64, f32, HashMap<String, Value>)>> {
+        filter: Option<&Filter>,
+        with_vector: bool,
+    ) -> Result<Vec<SearchHit>> {
         let url = format!("{}/collections/{}/points/search", self.base_url, self.collection_name);
         
         let request = SearchRequest {
@@ -265,6 +584,8 @@ impl QdrantService {
             limit: top_k,
             with_payload: true,
             score_threshold,
+            with_vector,
+            filter: filter.filter(|f| !f.is_empty()).cloned(),
         };
 
         let response = self.client
@@ -281,25 +602,312 @@ impl QdrantService {
 
         let search_response: SearchResponse = response.json().await?;
         
-        let results: Vec<(u64, f32, HashMap<String, Value>)> = search_response.result
+        let results: Vec<SearchHit> = search_response.result
             .into_iter()
-            .map(|hit| {
-                let id = hit.id;
-                let score = hit.score;
-                
-                // Convert Map<String, Value> to HashMap<String, Value>
-                let payload: HashMap<String, Value> = hit.payload
-                    .into_iter()
-                    .collect();
-
-                (id, score, payload)
-            })
+            .map(SearchHit::from)
             .collect();
 
         debug!("Found {} results for search query", results.len());
         Ok(results)
     }
 
+    /// Search the BM25 sparse vectors for chunks sharing terms with the query.
+    pub async fn search_sparse(
+        &self,
+        vector: SparseVector,
+        top_k: u64,
+        filter: Option<&Filter>,
+        with_vector: bool,
+    ) -> Result<Vec<SearchHit>> {
+        let url = format!("{}/collections/{}/points/search", self.base_url, self.collection_name);
+
+        let request = SparseSearchRequest {
+            vector: NamedSparseVector {
+                name: SPARSE_VECTOR_NAME.to_string(),
+                vector,
+            },
+            limit: top_k,
+            with_payload: true,
+            with_vector,
+            filter: filter.filter(|f| !f.is_empty()).cloned(),
+        };
+
+        let response = self.client
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .json(&request)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            return Err(anyhow!("API error searching sparse vectors: {}", error_text));
+        }
+
+        let search_response: SearchResponse = response.json().await?;
+        let results: Vec<SearchHit> = search_response.result
+            .into_iter()
+            .map(SearchHit::from)
+            .collect();
+
+        debug!("Found {} keyword results for search query", results.len());
+        Ok(results)
+    }
+
+    /// Search dense and sparse vectors and fuse both rankings with reciprocal rank fusion.
+    ///
+    /// The score threshold applies to the dense results only, so exact keyword
+    /// matches such as error codes are kept even when their cosine score is low.
+    /// The fused ranking decides the order, but each hit is scored by its cosine
+    /// similarity to the query so scores stay comparable with the threshold.
+    /// Collections without sparse vectors fall back to dense search.
+    pub async fn hybrid_search(
+        &self,
+        vector: Vec<f32>,
+        sparse_vector: SparseVector,
+        top_k: u64,
+        score_threshold: f32,
+        filter: Option<&Filter>,
+        with_vector: bool,
+    ) -> Result<Vec<SearchHit>> {
+        if !self.sparse_vectors || sparse_vector.is_empty() {
+            return self.search(vector, top_k, score_threshold, filter, with_vector).await;
+        }
+
+        // Vectors are needed to score keyword-only hits against the query
+        let (dense, sparse) = tokio::try_join!(
+            self.search(vector.clone(), top_k, score_threshold, filter, true),
+            self.search_sparse(sparse_vector, top_k, filter, true),
+        )?;
+
+        let mut fused = reciprocal_rank_fusion(vec![dense, sparse], RRF_K);
+        fused.truncate(top_k as usize);
+        for hit in fused.iter_mut() {
+            hit.score = hit
+                .vector
+                .as_deref()
+                .map_or(0.0, |hit_vector| cosine_similarity(&vector, hit_vector));
+            if !with_vector {
+                hit.vector = None;
+            }
+        }
+        Ok(fused)
+    }
+
+    /// Collect the indexing state of every source stored in the collection.
+    pub async fn list_sources(&self) -> Result<HashMap<String, SourceState>> {
+        let url = format!("{}/collections/{}/points/scroll", self.base_url, self.collection_name);
+        let mut sources: HashMap<String, SourceState> = HashMap::new();
+        let mut offset = None;
+
+        loop {
+            let request = ScrollRequest {
+                filter: None,
+                limit: 256,
+                with_payload: json!(["source", "source_hash", "source_mtime"]),
+                with_vector: false,
+                offset,
+            };
+
+            let response = self.client
+                .post(&url)
+                .header("Content-Type", "application/json")
+                .json(&request)
+                .send()
+                .await?;
+
+            if !response.status().is_success() {
+                let error_text = response.text().await?;
+                return Err(anyhow!("API error scrolling points: {}", error_text));
+            }
+
+            let scroll_response: ScrollResponse = response.json().await?;
+            for point in scroll_response.result.points {
+                let payload = point.payload.unwrap_or_default();
+                let source = match payload.get("source").and_then(|s| s.as_str()) {
+                    Some(source) => source.to_string(),
+                    None => continue,
+                };
+
+                sources.entry(source).or_default().add_point(&payload);
+            }
+
+            match scroll_response.result.next_page_offset {
+                Some(next) => offset = Some(next),
+                None => break,
+            }
+        }
+
+        debug!("Found {} sources in collection '{}'", sources.len(), self.collection_name);
+        Ok(sources)
+    }
+
+    /// Look up the indexing state of one source from a single one of its points.
+    ///
+    /// Every point of a source carries the same hash and modification time, so
+    /// `points` is at most 1. Returns `None` if the source has no points.
+    pub async fn source_state(&self, source: &str) -> Result<Option<SourceState>> {
+        let url = format!("{}/collections/{}/points/scroll", self.base_url, self.collection_name);
+        let request = ScrollRequest {
+            filter: Some(Filter::new().must(Condition::source(source))),
+            limit: 1,
+            with_payload: json!(["source_hash", "source_mtime"]),
+            with_vector: false,
+            offset: None,
+        };
+
+        let response = self.client
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .json(&request)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            return Err(anyhow!("API error scrolling points: {}", error_text));
+        }
+
+        let scroll_response: ScrollResponse = response.json().await?;
+        Ok(scroll_response.result.points.into_iter().next().map(|point| {
+            let mut state = SourceState::default();
+            state.add_point(&point.payload.unwrap_or_default());
+            state
+        }))
+    }
+
+    /// Fetch the payloads of the chunks of one version of a source whose
+    /// `chunk_index` lies in `indices`, ordered by index.
+    ///
+    /// Sources such as stdin input share a name across versions, so chunks are
+    /// matched on the content hash too; each index is returned at most once.
+    pub async fn get_chunks(
+        &self,
+        source: &str,
+        source_hash: &str,
+        indices: RangeInclusive<u64>,
+    ) -> Result<Vec<HashMap<String, Value>>> {
+        let url = format!("{}/collections/{}/points/scroll", self.base_url, self.collection_name);
+
+        let bounds = Bounds {
+            gte: Some(*indices.start() as f64),
+            lte: Some(*indices.end() as f64),
+            ..Bounds::default()
+        };
+        let request = ScrollRequest {
+            filter: Some(
+                Filter::new()
+                    .must(Condition::source(source))
+                    .must(Condition::matches("source_hash", source_hash))
+                    .must(Condition::range("chunk_index", bounds)),
+            ),
+            // Leave room for duplicates, e.g. identical files embedded under one name
+            limit: 2 * (indices.end().saturating_sub(*indices.start()) + 1),
+            with_payload: json!(true),
+            with_vector: false,
+            offset: None,
+        };
+
+        let response = self.client
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .json(&request)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            return Err(anyhow!("API error scrolling points: {}", error_text));
+        }
+
+        let scroll_response: ScrollResponse = response.json().await?;
+        let mut chunks: Vec<HashMap<String, Value>> = scroll_response.result.points
+            .into_iter()
+            .filter_map(|point| point.payload)
+            .map(|payload| payload.into_iter().collect())
+            .collect();
+        chunks.sort_by_key(|chunk| chunk.get("chunk_index").and_then(|i| i.as_u64()));
+        chunks.dedup_by_key(|chunk| chunk.get("chunk_index").and_then(|i| i.as_u64()));
+
+        debug!("Fetched {} chunks {:?} of '{}'", chunks.len(), indices, source);
+        Ok(chunks)
+    }
+
+    /// Delete all points of a source, optionally keeping those of one content version.
+    pub async fn delete_source(&self, source: &str, keep_hash: Option<&str>) -> Result<()> {
+        let url = format!("{}/collections/{}/points/delete", self.base_url, self.collection_name);
+
+        let mut filter = Filter::new().must(Condition::source(source));
+        if let Some(hash) = keep_hash {
+            filter = filter.must_not(Condition::matches("source_hash", hash));
+        }
+
+        let response = self.client
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .json(&DeleteRequest { filter })
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            return Err(anyhow!("API error deleting points: {}", error_text));
+        }
+
+        debug!("Deleted stale points of '{}' from collection '{}'", source, self.collection_name);
+        Ok(())
+    }
+
+    /// Record a new modification time on all points of a source.
+    pub async fn set_source_mtime(&self, source: &str, mtime: u64) -> Result<()> {
+        let url = format!("{}/collections/{}/points/payload", self.base_url, self.collection_name);
+
+        let request = SetPayloadRequest {
+            payload: json!({ "source_mtime": mtime }),
+            filter: Filter::new().must(Condition::source(source)),
+        };
+
+        let response = self.client
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .json(&request)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            return Err(anyhow!("API error setting payload: {}", error_text));
+        }
+
+        debug!("Updated modification time of '{}' to {}", source, mtime);
+        Ok(())
+    }
+
+    /// Delete all points of a source except the given ones.
+    pub async fn delete_source_except(&self, source: &str, keep_ids: Vec<PointId>) -> Result<()> {
+        let url = format!("{}/collections/{}/points/delete", self.base_url, self.collection_name);
+
+        let mut filter = Filter::new().must(Condition::source(source));
+        if !keep_ids.is_empty() {
+            filter = filter.must_not(Condition::has_id(keep_ids));
+        }
+
+        let response = self.client
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .json(&DeleteRequest { filter })
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            return Err(anyhow!("API error deleting points: {}", error_text));
+        }
+
+        debug!("Deleted stale points of '{}' from collection '{}'", source, self.collection_name);
+        Ok(())
+    }
+
     /// Check if the collection exists.
     pub async fn check_collection_exists(&self) -> Result<bool> {
         let collections = self.list_collections().await?;
@@ -307,3 +915,60 @@ impl QdrantService {
         Ok(exists)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(id: u64, score: f32) -> SearchHit {
+        SearchHit {
+            id: PointId::Num(id),
+            score,
+            payload: HashMap::new(),
+            vector: None,
+            rerank_score: None,
+        }
+    }
+
+    fn ids(hits: &[SearchHit]) -> Vec<u64> {
+        hits.iter()
+            .map(|hit| match hit.id {
+                PointId::Num(id) => id,
+                PointId::Uuid(_) => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rrf_rewards_points_ranked_in_both_lists() {
+        let dense = vec![hit(1, 0.9), hit(2, 0.8), hit(3, 0.7)];
+        let sparse = vec![hit(3, 12.0), hit(1, 9.0), hit(4, 3.0)];
+        let fused = reciprocal_rank_fusion(vec![dense, sparse], 60.0);
+
+        assert_eq!(ids(&fused), [1, 3, 2, 4]);
+        assert!((fused[0].score - (1.0 / 61.0 + 1.0 / 62.0)).abs() < 1e-6);
+        assert!((fused[2].score - 1.0 / 62.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rrf_keeps_single_list_order() {
+        let fused = reciprocal_rank_fusion(vec![vec![hit(5, 0.1), hit(6, 0.9)], Vec::new()], 60.0);
+        assert_eq!(ids(&fused), [5, 6]);
+        assert!(reciprocal_rank_fusion(Vec::new(), 60.0).is_empty());
+    }
+
+    #[test]
+    fn cosine_similarity_handles_scale_and_zero_vectors() {
+        assert!((cosine_similarity(&[1.0, 0.0], &[3.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 2.0]).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 1.0]), 0.0);
+    }
+
+    #[test]
+    fn record_ids_are_stable_and_typed() {
+        let id = PointId::for_record("tickets", &json!("1"));
+        assert_eq!(id, PointId::for_record("tickets", &json!("1")));
+        assert_ne!(id, PointId::for_record("tickets", &json!(1)));
+        assert_ne!(id, PointId::for_record("other", &json!("1")));
+    }
+}
@@ -0,0 +1,159 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+use crate::clients::embedding::fnv1a;
+use crate::config::{BM25_AVG_DOC_LENGTH, BM25_B, BM25_K1};
+
+/// Sparse vector in Qdrant's format: parallel lists of term indices and weights.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SparseVector {
+    pub indices: Vec<u32>,
+    pub values: Vec<f32>,
+}
+
+impl SparseVector {
+    /// Whether the vector has no terms.
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+}
+
+/// Words (including identifiers such as `E-1042`, `v2.3.1` or `ERR_TIMEOUT`).
+static TERM: Lazy<Regex> = Lazy::new(|| Regex::new(r"[\p{L}\p{N}_]+(?:[-./:#][\p{L}\p{N}_]+)*").unwrap());
+
+/// Common English words that carry no weight in keyword matching.
+static STOP_WORDS: Lazy<HashSet<&'static str>> = Lazy::new(|| {
+    [
+        "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "from", "has", "have",
+        "how", "i", "in", "is", "it", "its", "of", "on", "or", "that", "the", "this", "to",
+        "was", "what", "when", "where", "which", "who", "why", "will", "with", "you", "your",
+    ]
+    .into_iter()
+    .collect()
+});
+
+/// Split text into lowercase terms for keyword matching.
+///
+/// Compound identifiers are kept whole and also split into their parts, so
+/// `ticket-4711` matches queries for either `ticket-4711` or `4711`.
+pub fn tokenize(text: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    for term in TERM.find_iter(text) {
+        let term = term.as_str().to_lowercase();
+        if term.contains(|c: char| "-./:#".contains(c)) {
+            terms.extend(
+                term.split(|c: char| "-./:#".contains(c))
+                    .filter(|part| !part.is_empty())
+                    .map(str::to_string),
+            );
+        }
+        terms.push(term);
+    }
+    terms.retain(|term| !STOP_WORDS.contains(term.as_str()));
+    terms
+}
+
+/// Encoder producing BM25 sparse vectors for keyword search.
+///
+/// Documents are weighted with the BM25 term-frequency component; the inverse
+/// document frequency is applied by Qdrant through the `idf` modifier of the
+/// sparse vector, so query terms all carry weight 1.
+pub struct Bm25Encoder {
+    k1: f32,
+    b: f32,
+    avg_doc_length: f32,
+}
+
+impl Default for Bm25Encoder {
+    fn default() -> Self {
+        Self::new(BM25_K1, BM25_B, BM25_AVG_DOC_LENGTH)
+    }
+}
+
+impl Bm25Encoder {
+    /// Create an encoder with the given BM25 parameters.
+    pub fn new(k1: f32, b: f32, avg_doc_length: f32) -> Self {
+        Self {
+            k1,
+            b,
+            avg_doc_length: avg_doc_length.max(1.0),
+        }
+    }
+
+    /// Encode a document chunk for storage.
+    pub fn encode_document(&self, text: &str) -> SparseVector {
+        let terms = tokenize(text);
+        let doc_length = terms.len() as f32;
+
+        let mut frequencies: BTreeMap<u32, f32> = BTreeMap::new();
+        for term in &terms {
+            *frequencies.entry(term_index(term)).or_default() += 1.0;
+        }
+
+        let norm = self.k1 * (1.0 - self.b + self.b * doc_length / self.avg_doc_length);
+        let (indices, values) = frequencies
+            .into_iter()
+            .map(|(index, tf)| (index, tf * (self.k1 + 1.0) / (tf + norm)))
+            .unzip();
+        SparseVector { indices, values }
+    }
+
+    /// Encode a search query.
+    pub fn encode_query(&self, text: &str) -> SparseVector {
+        let indices: BTreeMap<u32, f32> = tokenize(text)
+            .iter()
+            .map(|term| (term_index(term), 1.0))
+            .collect();
+        let (indices, values) = indices.into_iter().unzip();
+        SparseVector { indices, values }
+    }
+}
+
+/// Index of a term in the sparse vector space.
+fn term_index(term: &str) -> u32 {
+    fnv1a(term.as_bytes()) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenize_keeps_identifiers_and_drops_stop_words() {
+        assert_eq!(
+            tokenize("What is the fix for ERR_TIMEOUT in v2.3?"),
+            ["fix", "err_timeout", "v2", "3", "v2.3"]
+        );
+        assert_eq!(tokenize("Ticket-4711"), ["ticket", "4711", "ticket-4711"]);
+        assert!(tokenize("the and of").is_empty());
+    }
+
+    #[test]
+    fn query_terms_have_unit_weight_once() {
+        let query = Bm25Encoder::default().encode_query("deploy Deploy rollback");
+        assert_eq!(query.indices.len(), 2);
+        assert_eq!(query.values, [1.0, 1.0]);
+        assert!(query.indices.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn document_weights_saturate_and_favour_short_documents() {
+        let encoder = Bm25Encoder::new(1.2, 0.75, 10.0);
+        let weight = |text: &str, term: &str| {
+            let vector = encoder.encode_document(text);
+            let position = vector.indices.iter().position(|i| *i == term_index(term)).unwrap();
+            vector.values[position]
+        };
+
+        let once = weight("deploy", "deploy");
+        let twice = weight("deploy deploy", "deploy");
+        let many = weight(&"deploy ".repeat(50), "deploy");
+        assert!(once < twice && twice < many);
+        assert!(many < 1.2 + 1.0, "term frequency saturates at k1 + 1");
+
+        let long = weight(&format!("deploy {}", "filler ".repeat(40)), "deploy");
+        assert!(long < once);
+    }
+}